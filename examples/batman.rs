@@ -11,6 +11,6 @@ fn a_lot_of_batman(_: &mut Request) -> IronResult<Response> {
 
 fn main() {
     let mut chain = Chain::new(a_lot_of_batman);
-    chain.link_after(CompressionMiddleware);
+    chain.link_after(CompressionMiddleware::new());
     Iron::new(chain).http("0.0.0.0:3000").unwrap();
 }
\ No newline at end of file
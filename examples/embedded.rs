@@ -0,0 +1,13 @@
+#[macro_use]
+extern crate iron_pack;
+extern crate iron;
+
+use iron::prelude::*;
+use iron_pack::EmbeddedAssets;
+
+fn main() {
+    let assets = EmbeddedAssets::new()
+        .asset("/index.html", include_compressed!("README.md"))
+        .asset("/batman.rs", include_compressed!("examples/batman.rs"));
+    Iron::new(assets).http("0.0.0.0:3000").unwrap();
+}
@@ -0,0 +1,58 @@
+//! The content-codings iron-pack produces, independent of iron. `iron-pack-macros` keeps a copy
+//! of this module and of `codec`, its tests check that the copies are in sync.
+
+use std::fmt;
+
+/// A content-coding the middleware is able to produce. Only the encodings enabled through
+/// cargo features exist.
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum CompressionEncoding {
+    /// Brotli, sent as `br`
+    #[cfg(feature = "brotli")]
+    Brotli,
+    /// Raw deflate, sent as `deflate`
+    #[cfg(feature = "deflate")]
+    Deflate,
+    /// Gzip, sent as `gzip`
+    #[cfg(feature = "gzip")]
+    Gzip,
+    /// Zstandard, sent as `zstd`
+    #[cfg(feature = "zstd")]
+    Zstd,
+}
+
+impl CompressionEncoding {
+    /// All enabled encodings in their default order of preference
+    pub fn all() -> Vec<CompressionEncoding> {
+        let mut encodings = vec![];
+        #[cfg(feature = "brotli")]
+        encodings.push(CompressionEncoding::Brotli);
+        #[cfg(feature = "zstd")]
+        encodings.push(CompressionEncoding::Zstd);
+        #[cfg(feature = "gzip")]
+        encodings.push(CompressionEncoding::Gzip);
+        #[cfg(feature = "deflate")]
+        encodings.push(CompressionEncoding::Deflate);
+        encodings
+    }
+
+    /// The content-coding token used in `Content-Encoding` and `Accept-Encoding` headers
+    pub fn content_coding(&self) -> &'static str {
+        match *self {
+            #[cfg(feature = "brotli")]
+            CompressionEncoding::Brotli => "br",
+            #[cfg(feature = "deflate")]
+            CompressionEncoding::Deflate => "deflate",
+            #[cfg(feature = "gzip")]
+            CompressionEncoding::Gzip => "gzip",
+            #[cfg(feature = "zstd")]
+            CompressionEncoding::Zstd => "zstd",
+        }
+    }
+}
+
+impl fmt::Display for CompressionEncoding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.content_coding())
+    }
+}
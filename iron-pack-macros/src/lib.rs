@@ -0,0 +1,127 @@
+//! The `include_compressed!` macro of iron-pack. Use it through `iron_pack::include_compressed!`
+//! with the `embed` feature of iron-pack.
+//!
+//! The variants are compressed by a copy of the `codec` and `encoding` modules of iron-pack, with
+//! the codecs and backends iron-pack forwards through cargo features. The tests check that the
+//! copies match the modules of iron-pack.
+
+#[cfg(feature = "brotli")]
+extern crate brotli;
+#[cfg(feature = "flate2")]
+extern crate flate2;
+#[cfg(feature = "libflate")]
+extern crate libflate;
+#[cfg(feature = "libdeflate")]
+extern crate libdeflater;
+#[cfg(feature = "zstd")]
+extern crate zstd;
+extern crate proc_macro;
+
+#[cfg(not(any(feature = "gzip", feature = "deflate", feature = "brotli", feature = "zstd")))]
+compile_error!("iron-pack-macros needs at least one of the `gzip`, `deflate`, `brotli` or `zstd` features");
+
+use std::env;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use proc_macro::{Delimiter, Literal, TokenStream, TokenTree};
+
+#[allow(dead_code)]
+mod codec;
+#[allow(dead_code)]
+mod encoding;
+
+/// Embeds a file, relative to the manifest directory of the crate, as
+/// `iron_pack::EmbeddedAsset` with its identity, brotli and gzip variants, compressed at the
+/// highest level of each codec. Variants of disabled codecs, and variants that are not smaller
+/// than the file, are left out.
+#[proc_macro]
+pub fn include_compressed(input: TokenStream) -> TokenStream {
+    match expand(input) {
+        Ok(output) => output,
+        Err(message) => format!("compile_error!({})", Literal::string(&message)).parse().expect("valid tokens"),
+    }
+}
+
+fn expand(input: TokenStream) -> Result<TokenStream, String> {
+    let path = parse_path(input)?;
+    let manifest_dir = env::var("CARGO_MANIFEST_DIR").map_err(|_| String::from("CARGO_MANIFEST_DIR is not set"))?;
+    let file = PathBuf::from(manifest_dir).join(&path);
+    let file_name = file.to_str().ok_or_else(|| format!("{} is not valid UTF-8", file.display()))?;
+    let data = fs::read(&file).map_err(|err| format!("{}: {}", file.display(), err))?;
+
+    let brotli = brotli_compress(&data).map_err(|err| format!("{}: {}", file.display(), err))?;
+    let gzip = gzip_compress(&data).map_err(|err| format!("{}: {}", file.display(), err))?;
+
+    // `include_bytes!` makes cargo rebuild the crate when the file changes
+    let output = format!("::iron_pack::EmbeddedAsset {{ path: {}, identity: include_bytes!({}), brotli: {}, gzip: {} }}",
+                         Literal::string(&path),
+                         Literal::string(file_name),
+                         variant(brotli.as_ref(), data.len()),
+                         variant(gzip.as_ref(), data.len()));
+    output.parse().map_err(|_| String::from("include_compressed! produced invalid tokens"))
+}
+
+/// Accepts a single string literal, possibly wrapped into an invisible group by `macro_rules!`
+fn parse_path(input: TokenStream) -> Result<String, String> {
+    let mut tokens = input.into_iter();
+    let path = match (tokens.next(), tokens.next()) {
+        (Some(TokenTree::Literal(literal)), None) => literal.to_string(),
+        (Some(TokenTree::Group(ref group)), None) if group.delimiter() == Delimiter::None => return parse_path(group.stream()),
+        _ => return Err(String::from("include_compressed! expects a single string literal")),
+    };
+    if path.len() < 2 || !path.starts_with('"') || !path.ends_with('"') || path.contains('\\') {
+        return Err(String::from("include_compressed! expects a string literal without escapes"));
+    }
+    Ok(path[1..path.len() - 1].to_owned())
+}
+
+fn variant(compressed: Option<&Vec<u8>>, length: usize) -> String {
+    match compressed {
+        Some(compressed) if compressed.len() < length => format!("Some({})", Literal::byte_string(compressed)),
+        _ => String::from("None"),
+    }
+}
+
+/// The brotli variant, compressed using the highest quality
+#[cfg(feature = "brotli")]
+fn brotli_compress(data: &[u8]) -> io::Result<Option<Vec<u8>>> {
+    use encoding::CompressionEncoding::Brotli;
+
+    codec::encode_with_level(Brotli, codec::max_level(Brotli), data).map(Some)
+}
+
+#[cfg(not(feature = "brotli"))]
+fn brotli_compress(_: &[u8]) -> io::Result<Option<Vec<u8>>> {
+    Ok(None)
+}
+
+/// The gzip variant, compressed using the highest level and the deterministic header of
+/// iron-pack: no modification time, unknown OS
+#[cfg(feature = "gzip")]
+fn gzip_compress(data: &[u8]) -> io::Result<Option<Vec<u8>>> {
+    use encoding::CompressionEncoding::Gzip;
+
+    codec::encode_gzip(codec::max_level(Gzip), &codec::GzipHeader::default(), data).map(Some)
+}
+
+#[cfg(not(feature = "gzip"))]
+fn gzip_compress(_: &[u8]) -> io::Result<Option<Vec<u8>>> {
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::Path;
+
+    #[test]
+    fn it_should_keep_the_copied_modules_in_sync_with_iron_pack() {
+        let manifest_dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+        for module in &["codec.rs", "encoding.rs"] {
+            let original = fs::read_to_string(manifest_dir.join("../src").join(module)).unwrap();
+            let copy = fs::read_to_string(manifest_dir.join("src").join(module)).unwrap();
+            assert!(copy == original, "iron-pack-macros/src/{} differs from src/{}, copy it over", module, module);
+        }
+    }
+}
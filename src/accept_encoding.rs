@@ -0,0 +1,82 @@
+//! Lenient parsing of the `Accept-Encoding` header.
+//!
+//! hyper rejects the whole header as soon as a single element is malformed, e.g. because of
+//! q-values out of range, misparses elements with whitespace around the `=` of a q-value and
+//! panics on elements that are empty apart from parameters. This module parses the raw header
+//! lines instead and recovers every coding that can be understood.
+
+use std::fmt;
+use std::str;
+use hyper::header::{Encoding, QualityItem, Quality};
+
+/// Returned when not a single content-coding could be recovered from the raw header
+#[derive(PartialEq, Clone, Debug)]
+pub struct MalformedAcceptEncoding(pub String);
+
+impl fmt::Display for MalformedAcceptEncoding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "no valid content-coding in Accept-Encoding header \"{}\"", self.0)
+    }
+}
+
+fn is_token_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || "!#$%&'*+-.^_`|~".contains(c)
+}
+
+fn parse_encoding(token: &str) -> Option<Encoding> {
+    let token = token.trim().to_ascii_lowercase();
+    if token.is_empty() || !token.chars().all(is_token_char) {
+        return None;
+    }
+    match &token[..] {
+        "chunked" => Some(Encoding::Chunked),
+        "deflate" => Some(Encoding::Deflate),
+        "gzip" => Some(Encoding::Gzip),
+        "compress" => Some(Encoding::Compress),
+        "identity" => Some(Encoding::Identity),
+        _ => Some(Encoding::EncodingExt(token)),
+    }
+}
+
+/// Parses a q-value, clamping numbers outside of `0..1`. Returns `None` for values that are not
+/// numbers at all, as the intent of the client can not be known in that case.
+fn parse_quality(value: &str) -> Option<Quality> {
+    let value = value.trim().trim_matches('"');
+    match value.parse::<f32>() {
+        Ok(q) if q.is_finite() => Some(Quality((q.clamp(0f32, 1f32) * 1000f32) as u16)),
+        _ => None,
+    }
+}
+
+fn parse_element(element: &str) -> Option<QualityItem<Encoding>> {
+    let mut parts = element.split(';');
+    let encoding = parts.next().and_then(parse_encoding)?;
+    let mut quality = Quality(1000);
+
+    for parameter in parts {
+        let mut key_value = parameter.splitn(2, '=');
+        let key = key_value.next().unwrap_or("").trim();
+        if key.eq_ignore_ascii_case("q") {
+            quality = parse_quality(key_value.next().unwrap_or(""))?;
+        }
+    }
+
+    Some(QualityItem::new(encoding, quality))
+}
+
+/// Parses raw `Accept-Encoding` header lines, skipping every element that can not be understood
+/// instead of rejecting the whole header.
+pub fn parse_lenient(raw: &[Vec<u8>]) -> Result<Vec<QualityItem<Encoding>>, MalformedAcceptEncoding> {
+    let lines: Vec<String> = raw.iter().map(|line| String::from_utf8_lossy(line).into_owned()).collect();
+    let items: Vec<QualityItem<Encoding>> = lines
+        .iter()
+        .flat_map(|line| line.split(','))
+        .filter(|element| !element.trim().is_empty())
+        .filter_map(parse_element)
+        .collect();
+
+    if items.is_empty() {
+        return Err(MalformedAcceptEncoding(lines.join(", ")));
+    }
+    Ok(items)
+}
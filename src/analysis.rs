@@ -0,0 +1,194 @@
+//! Measures how well, and how fast, each encoding and level compresses a set of files, to pick
+//! per-route presets from data. Bodies are compressed with `codec::encode_with_level`, exactly
+//! like the middleware compresses buffered responses.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use hyper::mime::Mime;
+use mime_guess;
+
+use codec;
+use negotiation::CompressionEncoding;
+
+/// The lowest level measured, level 0 only stores the data
+const MIN_LEVEL: u32 = 1;
+
+/// The totals of one encoding and level over all bodies of a content type
+#[derive(Clone, Debug)]
+pub struct Sample {
+    pub encoding: CompressionEncoding,
+    pub level: u32,
+    /// The sum of the compressed sizes
+    pub length: u64,
+    /// The sum of the encode times
+    pub duration: Duration,
+}
+
+/// The measurements of all bodies of a content type
+#[derive(Clone, Debug)]
+pub struct TypeReport {
+    pub content_type: String,
+    /// The number of bodies
+    pub bodies: usize,
+    /// The sum of the uncompressed sizes
+    pub length: u64,
+    /// One sample per encoding and level, ordered by encoding and level
+    pub samples: Vec<Sample>,
+}
+
+impl TypeReport {
+    fn new(content_type: &str) -> TypeReport {
+        let samples = CompressionEncoding::all()
+            .into_iter()
+            .flat_map(|encoding| (MIN_LEVEL..codec::max_level(encoding) + 1).map(move |level| (encoding, level)))
+            .map(|(encoding, level)| Sample { encoding, level, length: 0, duration: Duration::from_secs(0) })
+            .collect();
+        TypeReport { content_type: content_type.to_owned(), bodies: 0, length: 0, samples }
+    }
+}
+
+/// Collects measurements per content type
+#[derive(Clone, Debug, Default)]
+pub struct Analysis {
+    types: BTreeMap<String, TypeReport>,
+}
+
+impl Analysis {
+    pub fn new() -> Analysis {
+        Analysis::default()
+    }
+
+    /// Compresses a body with every encoding and level
+    pub fn add(&mut self, content_type: &str, data: &[u8]) -> io::Result<()> {
+        let report = self.types.entry(content_type.to_owned()).or_insert_with(|| TypeReport::new(content_type));
+        for sample in &mut report.samples {
+            let start = Instant::now();
+            let compressed = codec::encode_with_level(sample.encoding, sample.level, data)?;
+            sample.duration += start.elapsed();
+            sample.length += compressed.len() as u64;
+        }
+        report.bodies += 1;
+        report.length += data.len() as u64;
+        Ok(())
+    }
+
+    /// Adds a file, or every file below a directory. The content type is guessed from the file
+    /// extension unless given. Symbolic links below a directory are not followed.
+    pub fn add_path(&mut self, path: &Path, content_type: Option<&str>) -> io::Result<()> {
+        if fs::metadata(path)?.is_dir() {
+            self.add_dir(path, content_type)
+        } else {
+            self.add_file(path, content_type)
+        }
+    }
+
+    fn add_dir(&mut self, dir: &Path, content_type: Option<&str>) -> io::Result<()> {
+        let mut entries: Vec<(PathBuf, fs::FileType)> = fs::read_dir(dir)?
+            .map(|entry| entry.and_then(|entry| Ok((entry.path(), entry.file_type()?))))
+            .collect::<io::Result<_>>()?;
+        entries.sort_by(|a, b| a.0.cmp(&b.0));
+        for (path, file_type) in entries {
+            if file_type.is_dir() {
+                self.add_dir(&path, content_type)?;
+            } else if file_type.is_file() {
+                self.add_file(&path, content_type)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn add_file(&mut self, path: &Path, content_type: Option<&str>) -> io::Result<()> {
+        let data = fs::read(path)?;
+        match content_type {
+            Some(content_type) => self.add(content_type, &data),
+            None => {
+                let Mime(top, sub, _) = mime_guess::guess_mime_type(path);
+                self.add(&format!("{}/{}", top, sub), &data)
+            }
+        }
+    }
+
+    /// The reports ordered by content type
+    pub fn reports(&self) -> Vec<&TypeReport> {
+        self.types.values().collect()
+    }
+
+    /// A plain text table with one row per content type, encoding and level
+    pub fn to_table(&self) -> String {
+        let mut table = format!("{:<32} {:>6} {:<8} {:>5} {:>12} {:>12} {:>7} {:>10} {:>10}\n",
+                                "content-type", "bodies", "encoding", "level", "bytes", "compressed", "ratio", "time-ms", "MB/s");
+        for report in self.reports() {
+            for sample in &report.samples {
+                table.push_str(&format!("{:<32} {:>6} {:<8} {:>5} {:>12} {:>12} {:>6.1}% {:>10.3} {:>10.1}\n",
+                                        report.content_type,
+                                        report.bodies,
+                                        sample.encoding.content_coding(),
+                                        sample.level,
+                                        report.length,
+                                        sample.length,
+                                        ratio(sample.length, report.length),
+                                        millis(sample.duration),
+                                        throughput(report.length, sample.duration)));
+            }
+        }
+        table
+    }
+
+    /// The reports as JSON array
+    pub fn to_json(&self) -> String {
+        let reports: Vec<String> = self.reports()
+            .iter()
+            .map(|report| {
+                let samples: Vec<String> = report.samples
+                    .iter()
+                    .map(|sample| {
+                        format!("{{\"encoding\":\"{}\",\"level\":{},\"compressed\":{},\"ratio\":{:.4},\"time_ms\":{:.3}}}",
+                                sample.encoding.content_coding(),
+                                sample.level,
+                                sample.length,
+                                ratio(sample.length, report.length) / 100.0,
+                                millis(sample.duration))
+                    })
+                    .collect();
+                format!("{{\"content_type\":\"{}\",\"bodies\":{},\"bytes\":{},\"samples\":[{}]}}",
+                        json_escape(&report.content_type),
+                        report.bodies,
+                        report.length,
+                        samples.join(","))
+            })
+            .collect();
+        format!("[{}]", reports.join(","))
+    }
+}
+
+fn ratio(length: u64, original: u64) -> f64 {
+    if original == 0 {
+        100.0
+    } else {
+        length as f64 * 100.0 / original as f64
+    }
+}
+
+fn millis(duration: Duration) -> f64 {
+    duration.as_secs() as f64 * 1000.0 + f64::from(duration.subsec_nanos()) / 1_000_000.0
+}
+
+fn throughput(length: u64, duration: Duration) -> f64 {
+    let millis = millis(duration);
+    if millis == 0.0 {
+        0.0
+    } else {
+        length as f64 / 1000.0 / millis
+    }
+}
+
+fn json_escape(value: &str) -> String {
+    value.chars().flat_map(|c| match c {
+        '"' | '\\' => vec!['\\', c],
+        c if c.is_control() => format!("\\u{:04x}", c as u32).chars().collect(),
+        c => vec![c],
+    }).collect()
+}
@@ -0,0 +1,102 @@
+//! Reports the compressed size and encode time of files for every encoding and level, per
+//! content type.
+
+extern crate iron_pack;
+
+use std::env;
+use std::path::Path;
+use std::process;
+
+use iron_pack::analysis::Analysis;
+
+const USAGE: &str = "usage: iron-pack-analyze [--json] [--content-type TYPE] PATH...
+
+Compresses every file below each PATH with every encoding and level, and reports the compressed
+sizes and encode times per content type.
+
+  --json               print JSON instead of a table
+  --content-type TYPE  use TYPE for all files, e.g. for captured response bodies, instead of
+                       guessing it from the file extension";
+
+fn fail(message: &str) -> ! {
+    eprintln!("{}\n\n{}", message, USAGE);
+    process::exit(2);
+}
+
+/// What the command line asks for
+#[derive(PartialEq, Debug)]
+enum Command {
+    Help,
+    Analyze { json: bool, content_type: Option<String>, paths: Vec<String> },
+}
+
+fn parse_args<I: Iterator<Item = String>>(mut args: I) -> Result<Command, String> {
+    let mut json = false;
+    let mut content_type = None;
+    let mut paths = Vec::new();
+
+    while let Some(arg) = args.next() {
+        match &arg[..] {
+            "--json" => json = true,
+            "--content-type" => content_type = Some(args.next().ok_or("--content-type needs a type")?),
+            "-h" | "--help" => return Ok(Command::Help),
+            option if option.starts_with('-') => return Err(format!("unknown option {}", option)),
+            path => paths.push(path.to_owned()),
+        }
+    }
+    if paths.is_empty() {
+        return Err(String::from("no path given"));
+    }
+    Ok(Command::Analyze { json, content_type, paths })
+}
+
+fn main() {
+    let (json, content_type, paths) = match parse_args(env::args().skip(1)) {
+        Ok(Command::Help) => {
+            println!("{}", USAGE);
+            return;
+        }
+        Ok(Command::Analyze { json, content_type, paths }) => (json, content_type, paths),
+        Err(message) => fail(&message),
+    };
+
+    let mut analysis = Analysis::new();
+    for path in paths {
+        if let Err(err) = analysis.add_path(Path::new(&path), content_type.as_ref().map(|content_type| &content_type[..])) {
+            eprintln!("{}: {}", path, err);
+            process::exit(1);
+        }
+    }
+    if json {
+        println!("{}", analysis.to_json());
+    } else {
+        print!("{}", analysis.to_table());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_args, Command};
+
+    fn parse(args: &[&str]) -> Result<Command, String> {
+        parse_args(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn it_should_parse_the_options_and_paths() {
+        assert_eq!(parse(&["--json", "--content-type", "application/json", "bodies", "more"]), Ok(Command::Analyze {
+            json: true,
+            content_type: Some(String::from("application/json")),
+            paths: vec![String::from("bodies"), String::from("more")],
+        }));
+        assert_eq!(parse(&["bodies"]), Ok(Command::Analyze { json: false, content_type: None, paths: vec![String::from("bodies")] }));
+    }
+
+    #[test]
+    fn it_should_reject_invalid_command_lines() {
+        assert_eq!(parse(&[]), Err(String::from("no path given")));
+        assert_eq!(parse(&["bodies", "--content-type"]), Err(String::from("--content-type needs a type")));
+        assert_eq!(parse(&["--csv", "bodies"]), Err(String::from("unknown option --csv")));
+        assert_eq!(parse(&["--help"]), Ok(Command::Help));
+    }
+}
@@ -0,0 +1,169 @@
+//! Writes precompressed `.br`, `.gz` and `.zst` sidecars of the static files below directories,
+//! using the codec settings of the middleware.
+
+extern crate iron_pack;
+
+use std::env;
+use std::path::Path;
+use std::process;
+
+use iron_pack::RuleLevel;
+use iron_pack::precompress::{FileReport, Precompress};
+#[cfg(feature = "zstd")]
+use iron_pack::precompress::SidecarFormat;
+
+fn usage() -> String {
+    format!("usage: iron-pack-precompress [--max-effort] [--zstd] [--all-types] [--min-bytes BYTES] [--min-saving PERCENT] DIR...
+
+Writes precompressed sidecars of the files below each DIR.
+
+  --max-effort          compress using the highest level of each codec
+  --zstd                also write .zst sidecars, needs the zstd feature
+  --all-types           compress files of any content type
+  --min-bytes BYTES     skip files smaller than BYTES (default: {})
+  --min-saving PERCENT  only write sidecars at least PERCENT smaller than the file (default: 5)", Precompress::default().min_bytes)
+}
+
+fn fail(message: &str) -> ! {
+    eprintln!("{}\n\n{}", message, usage());
+    process::exit(2);
+}
+
+/// What the command line asks for
+#[derive(Debug)]
+enum Command {
+    Help,
+    Precompress(Precompress, Vec<String>),
+}
+
+fn parse<T: std::str::FromStr>(option: &str, value: Option<String>) -> Result<T, String> {
+    value
+        .and_then(|value| value.parse().ok())
+        .ok_or_else(|| format!("{} needs a number", option))
+}
+
+fn parse_args<I: Iterator<Item = String>>(mut args: I) -> Result<Command, String> {
+    let mut precompress = Precompress::default();
+    let mut dirs = Vec::new();
+
+    while let Some(arg) = args.next() {
+        match &arg[..] {
+            "--max-effort" => precompress.level = RuleLevel::Max,
+            #[cfg(feature = "zstd")]
+            "--zstd" => precompress.formats.push(SidecarFormat::Zstd),
+            #[cfg(not(feature = "zstd"))]
+            "--zstd" => return Err(String::from("iron-pack was built without the zstd feature")),
+            "--all-types" => precompress.all_types = true,
+            "--min-bytes" => precompress.min_bytes = parse(&arg, args.next())?,
+            "--min-saving" => precompress.min_saving = parse::<f64>(&arg, args.next())? / 100.0,
+            "-h" | "--help" => return Ok(Command::Help),
+            option if option.starts_with('-') => return Err(format!("unknown option {}", option)),
+            dir => dirs.push(dir.to_owned()),
+        }
+    }
+    if dirs.is_empty() {
+        return Err(String::from("no directory given"));
+    }
+    if precompress.formats.is_empty() {
+        return Err(String::from("iron-pack was built without the brotli, gzip and zstd features"));
+    }
+    Ok(Command::Precompress(precompress, dirs))
+}
+
+fn ratio(length: u64, original: u64) -> f64 {
+    length as f64 * 100.0 / original as f64
+}
+
+fn format_report(report: &FileReport) -> String {
+    match report.outcome {
+        Ok(ref sidecars) => {
+            let sidecars: Vec<String> = sidecars
+                .iter()
+                .map(|sidecar| {
+                    let skipped = if sidecar.written { "" } else { " (skipped)" };
+                    format!("{} {:.1}%{}", sidecar.format.extension(), ratio(sidecar.length, report.length), skipped)
+                })
+                .collect();
+            format!("{}: {} bytes, {}", report.path.display(), report.length, sidecars.join(", "))
+        }
+        Err(reason) => format!("{}: {} bytes, skipped: {}", report.path.display(), report.length, reason),
+    }
+}
+
+fn main() {
+    let (precompress, dirs) = match parse_args(env::args().skip(1)) {
+        Ok(Command::Help) => {
+            println!("{}", usage());
+            return;
+        }
+        Ok(Command::Precompress(precompress, dirs)) => (precompress, dirs),
+        Err(message) => fail(&message),
+    };
+
+    let (mut original, mut compressed) = (0, 0);
+    for dir in dirs {
+        let reports = precompress.dir(Path::new(&dir)).unwrap_or_else(|err| {
+            eprintln!("{}: {}", dir, err);
+            process::exit(1);
+        });
+        for report in reports {
+            println!("{}", format_report(&report));
+            if let Ok(ref sidecars) = report.outcome {
+                if let Some(smallest) = sidecars.iter().filter(|sidecar| sidecar.written).map(|sidecar| sidecar.length).min() {
+                    original += report.length;
+                    compressed += smallest;
+                }
+            }
+        }
+    }
+    if original > 0 {
+        println!("total: {} bytes, smallest sidecars {:.1}%", original, ratio(compressed, original));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use iron_pack::RuleLevel;
+    use iron_pack::precompress::{FileReport, Precompress};
+
+    use super::{format_report, parse_args, usage, Command};
+
+    fn parse(args: &[&str]) -> Result<Command, String> {
+        parse_args(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn it_should_parse_the_options_and_directories() {
+        match parse(&["--max-effort", "--all-types", "--min-bytes", "100", "--min-saving", "20", "public", "assets"]) {
+            Ok(Command::Precompress(precompress, dirs)) => {
+                assert_eq!(precompress.level, RuleLevel::Max);
+                assert!(precompress.all_types);
+                assert_eq!(precompress.min_bytes, 100);
+                assert_eq!(precompress.min_saving, 0.2);
+                assert_eq!(dirs, vec!["public", "assets"]);
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn it_should_reject_invalid_command_lines() {
+        assert_eq!(parse(&[]).unwrap_err(), "no directory given");
+        assert_eq!(parse(&["--min-bytes", "many", "public"]).unwrap_err(), "--min-bytes needs a number");
+        assert_eq!(parse(&["--fast", "public"]).unwrap_err(), "unknown option --fast");
+        assert!(matches!(parse(&["public", "--help"]), Ok(Command::Help)));
+    }
+
+    #[test]
+    fn it_should_document_the_minimum_size_of_the_middleware() {
+        assert!(usage().contains(&format!("(default: {})", Precompress::default().min_bytes)));
+    }
+
+    #[test]
+    fn it_should_report_skipped_files() {
+        let report = FileReport { path: PathBuf::from("public/logo.png"), length: 4096, outcome: Err("content-type") };
+        assert_eq!(format_report(&report), "public/logo.png: 4096 bytes, skipped: content-type");
+    }
+}
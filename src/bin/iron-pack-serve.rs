@@ -0,0 +1,188 @@
+//! Serves a directory with the compression behaviour of production deployments: precompressed
+//! sidecars where they exist, `CompressionMiddleware` for everything else.
+
+extern crate iron;
+extern crate iron_pack;
+
+use std::env;
+use std::io;
+use std::path::Path;
+use std::process;
+
+use iron::Iron;
+use iron::headers::CacheDirective;
+use iron_pack::{CompressionMiddleware, Rule, RuleLevel, StaticFiles};
+use iron_pack::precompress::Precompress;
+
+const USAGE: &str = "usage: iron-pack-serve [--bind ADDRESS] [--level LEVEL] [--max-age SECONDS] [--precompress] DIR
+
+Serves the files below DIR, preferring precompressed .br and .gz sidecars.
+
+  --bind ADDRESS     the address to listen on (default: 127.0.0.1:3000)
+  --level LEVEL      compress using fastest, default, max or a native level of each codec
+                     (default: default)
+  --max-age SECONDS  send Cache-Control: public, max-age=SECONDS (default: no-cache)
+  --precompress      rewrite the sidecars of every file before serving, like
+                     iron-pack-precompress, using the level of --level";
+
+fn fail(message: &str) -> ! {
+    eprintln!("{}\n\n{}", message, USAGE);
+    process::exit(2);
+}
+
+/// How to serve a directory
+#[derive(PartialEq, Debug)]
+struct Options {
+    bind: String,
+    level: Option<RuleLevel>,
+    cache_control: Vec<CacheDirective>,
+    precompress: bool,
+    dir: String,
+}
+
+/// What the command line asks for
+#[derive(PartialEq, Debug)]
+enum Command {
+    Help,
+    Serve(Options),
+}
+
+fn parse_level(level: &str) -> Result<RuleLevel, String> {
+    match level {
+        "fastest" => Ok(RuleLevel::Fastest),
+        "default" => Ok(RuleLevel::Default),
+        "max" => Ok(RuleLevel::Max),
+        level => level.parse().map(RuleLevel::Exact).map_err(|_| format!("invalid level {}", level)),
+    }
+}
+
+fn parse_args<I: Iterator<Item = String>>(mut args: I) -> Result<Command, String> {
+    let mut bind = String::from("127.0.0.1:3000");
+    let mut level = None;
+    let mut cache_control = vec![CacheDirective::NoCache];
+    let mut precompress = false;
+    let mut dir = None;
+
+    while let Some(arg) = args.next() {
+        match &arg[..] {
+            "--bind" => bind = args.next().ok_or("--bind needs an address")?,
+            "--level" => level = Some(parse_level(&args.next().ok_or("--level needs a level")?)?),
+            "--max-age" => {
+                let max_age = args.next().and_then(|max_age| max_age.parse().ok()).ok_or("--max-age needs a number")?;
+                cache_control = vec![CacheDirective::Public, CacheDirective::MaxAge(max_age)];
+            }
+            "--precompress" => precompress = true,
+            "-h" | "--help" => return Ok(Command::Help),
+            option if option.starts_with('-') => return Err(format!("unknown option {}", option)),
+            path if dir.is_none() => dir = Some(path.to_owned()),
+            _ => return Err(String::from("only one directory can be served")),
+        }
+    }
+    let dir = dir.ok_or("no directory given")?;
+    Ok(Command::Serve(Options { bind, level, cache_control, precompress, dir }))
+}
+
+/// Rewrites the sidecars if asked to, and creates the handler serving the directory
+fn handler(options: &Options) -> io::Result<StaticFiles> {
+    if options.precompress {
+        let precompress = Precompress { level: options.level.unwrap_or(RuleLevel::Default), ..Precompress::default() };
+        precompress.dir(Path::new(&options.dir))?;
+    }
+
+    let mut middleware = CompressionMiddleware::new();
+    if let Some(level) = options.level {
+        middleware = middleware.rule("/**", Rule::compress().level(level));
+    }
+    Ok(StaticFiles::with_middleware(&options.dir, middleware).cache_control(options.cache_control.clone()))
+}
+
+fn main() {
+    let options = match parse_args(env::args().skip(1)) {
+        Ok(Command::Help) => {
+            println!("{}", USAGE);
+            return;
+        }
+        Ok(Command::Serve(options)) => options,
+        Err(message) => fail(&message),
+    };
+    if !Path::new(&options.dir).is_dir() {
+        fail(&format!("{} is not a directory", options.dir));
+    }
+
+    let handler = handler(&options).unwrap_or_else(|err| {
+        eprintln!("{}: {}", options.dir, err);
+        process::exit(1);
+    });
+
+    println!("Serving {} on http://{}", options.dir, options.bind);
+    if let Err(err) = Iron::new(handler).http(&options.bind[..]) {
+        eprintln!("{}: {}", options.bind, err);
+        process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate iron_test;
+
+    use std::env;
+    use std::fs;
+    use iron::headers::{CacheControl, CacheDirective, Headers};
+    use iron_pack::{CompressionEncoding, RuleLevel};
+    use iron_pack::codec::{decode, encode_with_level};
+    use self::iron_test::{request, response};
+
+    use super::{handler, parse_args, Command, Options};
+
+    fn parse(args: &[&str]) -> Result<Command, String> {
+        parse_args(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn it_should_parse_the_options_and_directory() {
+        assert_eq!(parse(&["--bind", "0.0.0.0:80", "--level", "max", "--max-age", "60", "--precompress", "public"]), Ok(Command::Serve(Options {
+            bind: String::from("0.0.0.0:80"),
+            level: Some(RuleLevel::Max),
+            cache_control: vec![CacheDirective::Public, CacheDirective::MaxAge(60)],
+            precompress: true,
+            dir: String::from("public"),
+        })));
+        assert_eq!(parse(&["--level", "4", "public"]).map(|command| match command {
+            Command::Serve(options) => options.level,
+            Command::Help => None,
+        }), Ok(Some(RuleLevel::Exact(4))));
+    }
+
+    #[test]
+    fn it_should_reject_invalid_command_lines() {
+        assert_eq!(parse(&[]), Err(String::from("no directory given")));
+        assert_eq!(parse(&["--level", "slow", "public"]), Err(String::from("invalid level slow")));
+        assert_eq!(parse(&["public", "assets"]), Err(String::from("only one directory can be served")));
+        assert_eq!(parse(&["--help"]), Ok(Command::Help));
+    }
+
+    #[cfg(feature = "brotli")]
+    #[test]
+    fn it_should_precompress_using_the_level_and_serve_the_sidecars() {
+        let content: Vec<u8> = "I am Batman! ".bytes().cycle().take(4096).collect();
+        let dir = env::temp_dir().join(format!("iron-pack-serve-{}", ::std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("index.html"), &content).unwrap();
+
+        let options = match parse(&["--level", "fastest", "--max-age", "60", "--precompress", dir.to_str().unwrap()]) {
+            Ok(Command::Serve(options)) => options,
+            other => panic!("unexpected {:?}", other),
+        };
+        let handler = handler(&options).unwrap();
+        let sidecar = fs::read(dir.join("index.html.br")).unwrap();
+        assert_eq!(sidecar, encode_with_level(CompressionEncoding::Brotli, 1, &content).unwrap());
+
+        let mut headers = Headers::new();
+        headers.set_raw("Accept-Encoding", vec![b"br".to_vec()]);
+        let res = request::get("http://localhost:3000/", headers, &handler).unwrap();
+        assert_eq!(res.headers.get::<CacheControl>(), Some(&CacheControl(vec![CacheDirective::Public, CacheDirective::MaxAge(60)])));
+        assert_eq!(res.headers.get_raw("Content-Encoding"), Some(&[b"br".to_vec()][..]));
+        assert_eq!(decode(CompressionEncoding::Brotli, &response::extract_body_to_bytes(res)).unwrap(), content);
+    }
+}
@@ -0,0 +1,133 @@
+//! Mitigations against BREACH, which recovers secrets from the compressed size of responses
+//! that also reflect attacker-controlled input.
+
+use std::fmt;
+use hyper;
+use hyper::header::{Header, HeaderFormat, Headers, Host};
+#[cfg(feature = "padding")]
+use rand::{self, Rng};
+
+/// **BREACH Mitigation**
+///
+/// An attack needs a victim's browser to send many requests on behalf of another site, and to
+/// observe the size of the compressed responses. Each mitigation is disabled by default.
+///
+/// # Example
+/// ```rust
+/// use iron_pack::{BreachMitigation, Config};
+///
+/// let config = Config::default().breach_mitigation(BreachMitigation::default()
+///     .skip_cross_site(true)
+///     .skip_sensitive(true));
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct BreachMitigation {
+    pub(crate) skip_cross_site: bool,
+    pub(crate) skip_sensitive: bool,
+    pub(crate) max_padding: usize,
+}
+
+impl BreachMitigation {
+    /// Do not compress responses to cross-site requests, as told by the `Sec-Fetch-Site`
+    /// header, or by an `Origin` that differs from the `Host`
+    pub fn skip_cross_site(mut self, enabled: bool) -> BreachMitigation {
+        self.skip_cross_site = enabled;
+        self
+    }
+
+    /// Do not compress responses that handlers marked as `Sensitive`
+    pub fn skip_sensitive(mut self, enabled: bool) -> BreachMitigation {
+        self.skip_sensitive = enabled;
+        self
+    }
+
+    /// Hide the exact length of compressed responses by adding between 0 and `max_bytes` of
+    /// random padding to them (default: 0, disabled)
+    ///
+    /// The padding is stored as gzip header comment, and brotli and deflate have no place for
+    /// it. So only gzip is offered while padding is enabled: clients that accept gzip get it
+    /// even if they prefer another encoding, and responses to other clients are skipped as
+    /// `SkipReason::Padding`. It makes an attack slower, rather than impossible.
+    #[cfg(feature = "padding")]
+    pub fn padding(mut self, max_bytes: usize) -> BreachMitigation {
+        self.max_padding = max_bytes;
+        self
+    }
+
+    pub(crate) fn pads(&self) -> bool {
+        self.max_padding > 0
+    }
+}
+
+/// Marks a response as reflecting secrets, such as CSRF tokens, next to user input. Add it to
+/// an iron response with `Response::with` or `Response::set_mut`. hyper responses are marked by
+/// setting it as header, which `hyper_adapter` removes before the response is sent.
+///
+/// # Example
+/// ```rust
+/// extern crate iron;
+/// extern crate iron_pack;
+///
+/// use iron::prelude::*;
+/// use iron::status;
+/// use iron_pack::Sensitive;
+///
+/// fn account(_: &mut Request) -> IronResult<Response> {
+///     Ok(Response::with((status::Ok, "<input name=\"csrf\" value=\"...\">", Sensitive)))
+/// }
+/// # fn main() {}
+/// ```
+#[derive(Clone, Copy, Debug)]
+pub struct Sensitive;
+
+impl Header for Sensitive {
+    fn header_name() -> &'static str {
+        "X-Iron-Pack-Sensitive"
+    }
+
+    fn parse_header(_: &[Vec<u8>]) -> hyper::Result<Sensitive> {
+        Ok(Sensitive)
+    }
+}
+
+impl HeaderFormat for Sensitive {
+    fn fmt_header(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("1")
+    }
+}
+
+/// Whether a request was sent on behalf of another site. Requests without `Sec-Fetch-Site`
+/// and `Origin` headers count as same-site.
+pub fn is_cross_site(request_headers: &Headers) -> bool {
+    if let Some(site) = request_headers.get_raw("Sec-Fetch-Site").and_then(|values| values.first()) {
+        return site.as_slice() == b"cross-site";
+    }
+
+    let origin = match request_headers.get_raw("Origin").and_then(|values| values.first()) {
+        Some(origin) => String::from_utf8_lossy(origin).into_owned(),
+        None => return false,
+    };
+    let host = match request_headers.get::<Host>() {
+        Some(host) => match host.port {
+            Some(port) => format!("{}:{}", host.hostname, port),
+            None => host.hostname.clone(),
+        },
+        None => return true,
+    };
+
+    // Opaque origins are sent as `null`
+    let origin_host = match origin.find("://") {
+        Some(index) => &origin[index + 3..],
+        None => return true,
+    };
+    !origin_host.eq_ignore_ascii_case(&host)
+}
+
+/// Random printable characters of a random length up to `max_bytes`, drawn from the
+/// cryptographically secure generator of `rand` so clients can't predict them
+#[cfg(feature = "padding")]
+pub fn padding(max_bytes: usize) -> Vec<u8> {
+    let mut rng = rand::thread_rng();
+    let length = rng.gen_range(0..=max_bytes);
+    (0..length).map(|_| rng.gen_range(b'!'..=b'~')).collect()
+}
@@ -0,0 +1,531 @@
+//! Streaming encoders that work on any `io::Write`, and decoders that work on any `io::Read`,
+//! independent of iron.
+//!
+//! Which encoders exist depends on the enabled cargo features: `brotli`, `gzip`, `deflate` and
+//! `zstd` enable the codecs, `libflate` (default), `flate2-rust` and `flate2-zlib` select the backend
+//! used for gzip and deflate. With the `libdeflate` feature, complete buffers are compressed in
+//! one go using libdeflate instead of going through the streaming encoders.
+//!
+//! Compression levels are the native ones of each codec: 0 to 11 for brotli, 0 to 9 for gzip
+//! and deflate and 1 to 19 for zstd. libflate has no numeric levels, so they are mapped onto its options: level 0
+//! stores the data, levels 1 to 3 use fixed Huffman codes, levels 1 to 5 shrink the LZ77 window
+//! from 16 KiB down to 1 KiB, and levels 7 to 9 grow the block size, which only makes a
+//! difference for bodies above 1 MiB.
+
+use std::io;
+use std::io::{Read, Write};
+#[cfg(feature = "brotli")]
+use brotli;
+#[cfg(any(feature = "flate2-rust", feature = "flate2-zlib"))]
+use flate2;
+#[cfg(feature = "libflate")]
+use libflate;
+#[cfg(all(feature = "libdeflate", any(feature = "gzip", feature = "deflate")))]
+use libdeflater;
+#[cfg(all(feature = "libdeflate", any(feature = "gzip", feature = "deflate")))]
+use std::cell::RefCell;
+#[cfg(all(feature = "gzip", feature = "libflate", not(any(feature = "flate2-rust", feature = "flate2-zlib"))))]
+use std::ffi::CString;
+#[cfg(feature = "zstd")]
+use zstd;
+
+use encoding::CompressionEncoding;
+
+#[cfg(feature = "brotli")]
+const BROTLI_BUFFER_SIZE: usize = 4096;
+#[cfg(feature = "brotli")]
+const BROTLI_QUALITY: u32 = 8;
+#[cfg(feature = "brotli")]
+const BROTLI_MAX_QUALITY: u32 = 11;
+#[cfg(any(feature = "gzip", feature = "deflate"))]
+const FLATE_LEVEL: u32 = 6;
+#[cfg(any(feature = "gzip", feature = "deflate"))]
+const FLATE_MAX_LEVEL: u32 = 9;
+#[cfg(feature = "brotli")]
+const BROTLI_LG_WINDOW_SIZE: u32 = 20;
+#[cfg(feature = "zstd")]
+const ZSTD_LEVEL: u32 = 3;
+#[cfg(feature = "zstd")]
+const ZSTD_MAX_LEVEL: u32 = 19;
+
+/// A streaming encoder for one of the supported encodings.
+///
+/// Everything written to the encoder is compressed into the wrapped writer. `finish` has to be
+/// called once all data is written to complete the stream.
+///
+/// # Example
+/// ```rust
+/// use std::io::Write;
+/// use iron_pack::CompressionEncoding;
+/// use iron_pack::codec::Encoder;
+///
+/// # #[cfg(feature = "gzip")]
+/// # fn main() {
+/// let mut encoder = Encoder::new(CompressionEncoding::Gzip, Vec::new()).unwrap();
+/// encoder.write_all(b"Na na na na na na na na, Batman!").unwrap();
+/// let compressed: Vec<u8> = encoder.finish().unwrap();
+/// # }
+/// # #[cfg(not(feature = "gzip"))]
+/// # fn main() {}
+/// ```
+pub struct Encoder<W: Write>(Inner<W>);
+
+/// **gzip Header**
+///
+/// The fields of the gzip header. The default header has no modification time and an unknown
+/// OS, so identical input is compressed into identical output on every run and machine, as long
+/// as the same backend is used.
+///
+/// # Example
+/// ```rust
+/// use iron_pack::Config;
+/// use iron_pack::codec::GzipHeader;
+///
+/// # #[cfg(feature = "gzip")]
+/// # fn main() {
+/// let config = Config::default().gzip_header(GzipHeader {
+///     filename: Some(b"report.csv".to_vec()),
+///     ..GzipHeader::default()
+/// });
+/// # }
+/// # #[cfg(not(feature = "gzip"))]
+/// # fn main() {}
+/// ```
+#[cfg(feature = "gzip")]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GzipHeader {
+    /// The modification time as UNIX timestamp, 0 if unknown (default: 0)
+    pub mtime: u32,
+    /// The OS the data was compressed on, 255 if unknown (default: 255)
+    pub os: u8,
+    /// The `FNAME` field, without its terminating zero byte. Zero bytes are removed.
+    pub filename: Option<Vec<u8>>,
+    /// The `FCOMMENT` field, without its terminating zero byte. Zero bytes are removed.
+    pub comment: Option<Vec<u8>>,
+    /// The subfields of the `FEXTRA` field as pairs of ID and data
+    pub extra: Vec<([u8; 2], Vec<u8>)>,
+}
+
+#[cfg(feature = "gzip")]
+const GZIP_OS_UNKNOWN: u8 = 255;
+#[cfg(feature = "gzip")]
+const GZIP_FEXTRA: u8 = 4;
+#[cfg(feature = "gzip")]
+const GZIP_FNAME: u8 = 8;
+#[cfg(feature = "gzip")]
+const GZIP_FCOMMENT: u8 = 16;
+
+#[cfg(feature = "gzip")]
+impl Default for GzipHeader {
+    fn default() -> GzipHeader {
+        GzipHeader {
+            mtime: 0,
+            os: GZIP_OS_UNKNOWN,
+            filename: None,
+            comment: None,
+            extra: Vec::new(),
+        }
+    }
+}
+
+#[cfg(feature = "gzip")]
+impl GzipHeader {
+    /// The `FEXTRA` field without its length
+    fn extra_field(&self) -> Vec<u8> {
+        let mut field = Vec::new();
+        for &(id, ref data) in &self.extra {
+            field.extend_from_slice(&id);
+            field.extend_from_slice(&[data.len() as u8, (data.len() >> 8) as u8]);
+            field.extend_from_slice(data);
+        }
+        field
+    }
+
+    /// The header as written in front of the deflate stream
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut flags = 0;
+        if !self.extra.is_empty() {
+            flags |= GZIP_FEXTRA;
+        }
+        if self.filename.is_some() {
+            flags |= GZIP_FNAME;
+        }
+        if self.comment.is_some() {
+            flags |= GZIP_FCOMMENT;
+        }
+
+        let mtime = self.mtime;
+        let mut bytes = vec![0x1f, 0x8b, 0x08, flags, mtime as u8, (mtime >> 8) as u8, (mtime >> 16) as u8, (mtime >> 24) as u8, 0x00, self.os];
+        if !self.extra.is_empty() {
+            let extra = self.extra_field();
+            bytes.extend_from_slice(&[extra.len() as u8, (extra.len() >> 8) as u8]);
+            bytes.extend_from_slice(&extra);
+        }
+        for field in self.filename.iter().chain(self.comment.iter()) {
+            bytes.extend(without_zero_bytes(field));
+            bytes.push(0);
+        }
+        bytes
+    }
+}
+
+/// The level an encoding is compressed with unless configured otherwise
+pub fn default_level(encoding: CompressionEncoding) -> u32 {
+    match encoding {
+        #[cfg(feature = "brotli")]
+        CompressionEncoding::Brotli => BROTLI_QUALITY,
+        #[cfg(feature = "deflate")]
+        CompressionEncoding::Deflate => FLATE_LEVEL,
+        #[cfg(feature = "gzip")]
+        CompressionEncoding::Gzip => FLATE_LEVEL,
+        #[cfg(feature = "zstd")]
+        CompressionEncoding::Zstd => ZSTD_LEVEL,
+    }
+}
+
+/// The highest level of an encoding, higher levels are clamped to it
+pub fn max_level(encoding: CompressionEncoding) -> u32 {
+    match encoding {
+        #[cfg(feature = "brotli")]
+        CompressionEncoding::Brotli => BROTLI_MAX_QUALITY,
+        #[cfg(feature = "deflate")]
+        CompressionEncoding::Deflate => FLATE_MAX_LEVEL,
+        #[cfg(feature = "gzip")]
+        CompressionEncoding::Gzip => FLATE_MAX_LEVEL,
+        #[cfg(feature = "zstd")]
+        CompressionEncoding::Zstd => ZSTD_MAX_LEVEL,
+    }
+}
+
+#[cfg(all(any(feature = "gzip", feature = "deflate"), any(feature = "flate2-rust", feature = "flate2-zlib")))]
+fn flate2_compression(level: u32) -> flate2::Compression {
+    flate2::Compression::new(level.min(FLATE_MAX_LEVEL))
+}
+
+/// Maps a level onto the options of libflate, whose gzip and deflate options are distinct types
+/// with the same methods
+#[cfg(all(any(feature = "gzip", feature = "deflate"), feature = "libflate", not(any(feature = "flate2-rust", feature = "flate2-zlib"))))]
+macro_rules! libflate_options {
+    ($options:ty, $level:expr) => {{
+        let level = $level.min(FLATE_MAX_LEVEL);
+        let window_size = 1 << (9 + level.max(1).min(FLATE_LEVEL));
+        let block_size = libflate::deflate::DEFAULT_BLOCK_SIZE << level.saturating_sub(FLATE_LEVEL);
+        let options = <$options>::with_lz77(libflate::lz77::DefaultLz77Encoder::with_window_size(window_size)).block_size(block_size);
+        match level {
+            0 => options.no_compression(),
+            1..=3 => options.fixed_huffman_codes(),
+            _ => options,
+        }
+    }};
+}
+
+enum Inner<W: Write> {
+    #[cfg(feature = "brotli")]
+    Brotli(brotli::CompressorWriter<W>),
+    #[cfg(all(feature = "deflate", any(feature = "flate2-rust", feature = "flate2-zlib")))]
+    Deflate(flate2::write::DeflateEncoder<W>),
+    #[cfg(all(feature = "deflate", feature = "libflate", not(any(feature = "flate2-rust", feature = "flate2-zlib"))))]
+    Deflate(libflate::deflate::Encoder<W>),
+    #[cfg(all(feature = "gzip", any(feature = "flate2-rust", feature = "flate2-zlib")))]
+    Gzip(flate2::write::GzEncoder<W>),
+    #[cfg(all(feature = "gzip", feature = "libflate", not(any(feature = "flate2-rust", feature = "flate2-zlib"))))]
+    Gzip(libflate::gzip::Encoder<W>),
+    #[cfg(feature = "zstd")]
+    Zstd(zstd::stream::write::Encoder<'static, W>),
+}
+
+impl<W: Write> Encoder<W> {
+    /// Creates an encoder writing the compressed stream into `inner`
+    pub fn new(encoding: CompressionEncoding, inner: W) -> io::Result<Encoder<W>> {
+        Encoder::with_level(encoding, default_level(encoding), inner)
+    }
+
+    /// Creates an encoder using the given compression level
+    pub fn with_level(encoding: CompressionEncoding, level: u32, inner: W) -> io::Result<Encoder<W>> {
+        match encoding {
+            #[cfg(feature = "brotli")]
+            CompressionEncoding::Brotli => {
+                let quality = level.min(BROTLI_MAX_QUALITY);
+                Ok(Encoder(Inner::Brotli(brotli::CompressorWriter::new(inner, BROTLI_BUFFER_SIZE, quality, BROTLI_LG_WINDOW_SIZE))))
+            }
+            #[cfg(all(feature = "deflate", any(feature = "flate2-rust", feature = "flate2-zlib")))]
+            CompressionEncoding::Deflate => Ok(Encoder(Inner::Deflate(flate2::write::DeflateEncoder::new(inner, flate2_compression(level))))),
+            #[cfg(all(feature = "deflate", feature = "libflate", not(any(feature = "flate2-rust", feature = "flate2-zlib"))))]
+            CompressionEncoding::Deflate => {
+                let options = libflate_options!(libflate::deflate::EncodeOptions<_>, level);
+                Ok(Encoder(Inner::Deflate(libflate::deflate::Encoder::with_options(inner, options))))
+            }
+            #[cfg(feature = "gzip")]
+            CompressionEncoding::Gzip => Encoder::gzip(level, &GzipHeader::default(), inner),
+            // zstd reads level 0 as its default level
+            #[cfg(feature = "zstd")]
+            CompressionEncoding::Zstd => Ok(Encoder(Inner::Zstd(zstd::stream::write::Encoder::new(inner, level.max(1).min(ZSTD_MAX_LEVEL) as i32)?))),
+        }
+    }
+
+    /// Creates a gzip encoder writing the given header fields
+    #[cfg(all(feature = "gzip", any(feature = "flate2-rust", feature = "flate2-zlib")))]
+    pub fn gzip(level: u32, header: &GzipHeader, inner: W) -> io::Result<Encoder<W>> {
+        let mut builder = flate2::GzBuilder::new().mtime(header.mtime).operating_system(header.os);
+        if let Some(ref filename) = header.filename {
+            builder = builder.filename(without_zero_bytes(filename));
+        }
+        if let Some(ref comment) = header.comment {
+            builder = builder.comment(without_zero_bytes(comment));
+        }
+        if !header.extra.is_empty() {
+            builder = builder.extra(header.extra_field());
+        }
+        Ok(Encoder(Inner::Gzip(builder.write(inner, flate2_compression(level)))))
+    }
+
+    /// Creates a gzip encoder writing the given header fields
+    #[cfg(all(feature = "gzip", feature = "libflate", not(any(feature = "flate2-rust", feature = "flate2-zlib"))))]
+    pub fn gzip(level: u32, header: &GzipHeader, inner: W) -> io::Result<Encoder<W>> {
+        use libflate::gzip::{ExtraField, ExtraSubField, HeaderBuilder, Os};
+
+        let mut builder = HeaderBuilder::new();
+        builder.modification_time(header.mtime).os(Os::Undefined(header.os));
+        if let Some(ref filename) = header.filename {
+            builder.filename(CString::new(without_zero_bytes(filename)).expect("zero bytes are removed"));
+        }
+        if let Some(ref comment) = header.comment {
+            builder.comment(CString::new(without_zero_bytes(comment)).expect("zero bytes are removed"));
+        }
+        if !header.extra.is_empty() {
+            let subfields = header.extra.iter().map(|&(id, ref data)| ExtraSubField { id, data: data.clone() }).collect();
+            builder.extra_field(ExtraField { subfields });
+        }
+        let options = libflate_options!(libflate::gzip::EncodeOptions<_>, level);
+        Ok(Encoder(Inner::Gzip(libflate::gzip::Encoder::with_options(inner, options.header(builder.finish()))?)))
+    }
+
+    /// Completes the compressed stream and returns the wrapped writer
+    pub fn finish(self) -> io::Result<W> {
+        match self.0 {
+            #[cfg(feature = "brotli")]
+            Inner::Brotli(mut encoder) => {
+                encoder.flush()?;
+                Ok(encoder.into_inner())
+            }
+            #[cfg(all(feature = "deflate", any(feature = "flate2-rust", feature = "flate2-zlib")))]
+            Inner::Deflate(encoder) => encoder.finish(),
+            #[cfg(all(feature = "deflate", feature = "libflate", not(any(feature = "flate2-rust", feature = "flate2-zlib"))))]
+            Inner::Deflate(encoder) => encoder.finish().into_result(),
+            #[cfg(all(feature = "gzip", any(feature = "flate2-rust", feature = "flate2-zlib")))]
+            Inner::Gzip(encoder) => encoder.finish(),
+            #[cfg(all(feature = "gzip", feature = "libflate", not(any(feature = "flate2-rust", feature = "flate2-zlib"))))]
+            Inner::Gzip(encoder) => encoder.finish().into_result(),
+            #[cfg(feature = "zstd")]
+            Inner::Zstd(encoder) => encoder.finish(),
+        }
+    }
+
+    fn as_write(&mut self) -> &mut Write {
+        match self.0 {
+            #[cfg(feature = "brotli")]
+            Inner::Brotli(ref mut encoder) => encoder,
+            #[cfg(feature = "deflate")]
+            Inner::Deflate(ref mut encoder) => encoder,
+            #[cfg(feature = "gzip")]
+            Inner::Gzip(ref mut encoder) => encoder,
+            #[cfg(feature = "zstd")]
+            Inner::Zstd(ref mut encoder) => encoder,
+        }
+    }
+}
+
+impl<W: Write> Write for Encoder<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.as_write().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.as_write().flush()
+    }
+}
+
+/// A decoder reading the decompressed stream of an encoded reader
+pub struct Decoder<R: Read>(DecoderInner<R>);
+
+enum DecoderInner<R: Read> {
+    #[cfg(feature = "brotli")]
+    Brotli(brotli::Decompressor<R>),
+    #[cfg(all(feature = "deflate", any(feature = "flate2-rust", feature = "flate2-zlib")))]
+    Deflate(flate2::read::DeflateDecoder<R>),
+    #[cfg(all(feature = "deflate", feature = "libflate", not(any(feature = "flate2-rust", feature = "flate2-zlib"))))]
+    Deflate(libflate::deflate::Decoder<R>),
+    #[cfg(all(feature = "gzip", any(feature = "flate2-rust", feature = "flate2-zlib")))]
+    Gzip(flate2::read::GzDecoder<R>),
+    #[cfg(all(feature = "gzip", feature = "libflate", not(any(feature = "flate2-rust", feature = "flate2-zlib"))))]
+    Gzip(libflate::gzip::Decoder<R>),
+    #[cfg(feature = "zstd")]
+    Zstd(zstd::stream::read::Decoder<'static, io::BufReader<R>>),
+}
+
+impl<R: Read> Decoder<R> {
+    /// Creates a decoder reading the compressed stream from `inner`
+    pub fn new(encoding: CompressionEncoding, inner: R) -> io::Result<Decoder<R>> {
+        Ok(Decoder(match encoding {
+            #[cfg(feature = "brotli")]
+            CompressionEncoding::Brotli => DecoderInner::Brotli(brotli::Decompressor::new(inner, BROTLI_BUFFER_SIZE)),
+            #[cfg(all(feature = "deflate", any(feature = "flate2-rust", feature = "flate2-zlib")))]
+            CompressionEncoding::Deflate => DecoderInner::Deflate(flate2::read::DeflateDecoder::new(inner)),
+            #[cfg(all(feature = "deflate", feature = "libflate", not(any(feature = "flate2-rust", feature = "flate2-zlib"))))]
+            CompressionEncoding::Deflate => DecoderInner::Deflate(libflate::deflate::Decoder::new(inner)),
+            #[cfg(all(feature = "gzip", any(feature = "flate2-rust", feature = "flate2-zlib")))]
+            CompressionEncoding::Gzip => DecoderInner::Gzip(flate2::read::GzDecoder::new(inner)),
+            #[cfg(all(feature = "gzip", feature = "libflate", not(any(feature = "flate2-rust", feature = "flate2-zlib"))))]
+            CompressionEncoding::Gzip => DecoderInner::Gzip(libflate::gzip::Decoder::new(inner)?),
+            #[cfg(feature = "zstd")]
+            CompressionEncoding::Zstd => DecoderInner::Zstd(zstd::stream::read::Decoder::new(inner)?),
+        }))
+    }
+
+    fn as_read(&mut self) -> &mut Read {
+        match self.0 {
+            #[cfg(feature = "brotli")]
+            DecoderInner::Brotli(ref mut decoder) => decoder,
+            #[cfg(feature = "deflate")]
+            DecoderInner::Deflate(ref mut decoder) => decoder,
+            #[cfg(feature = "gzip")]
+            DecoderInner::Gzip(ref mut decoder) => decoder,
+            #[cfg(feature = "zstd")]
+            DecoderInner::Zstd(ref mut decoder) => decoder,
+        }
+    }
+}
+
+impl<R: Read> Read for Decoder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.as_read().read(buf)
+    }
+}
+
+/// Decompresses a complete buffer
+pub fn decode(encoding: CompressionEncoding, data: &[u8]) -> io::Result<Vec<u8>> {
+    let mut decoded = Vec::new();
+    Decoder::new(encoding, data)?.read_to_end(&mut decoded)?;
+    Ok(decoded)
+}
+
+/// Zero bytes terminate the fields of the gzip header
+#[cfg(feature = "gzip")]
+fn without_zero_bytes(field: &[u8]) -> Vec<u8> {
+    field.iter().cloned().filter(|&byte| byte != 0).collect()
+}
+
+#[cfg(all(feature = "libdeflate", any(feature = "gzip", feature = "deflate")))]
+thread_local!(static LIBDEFLATE_COMPRESSORS: RefCell<Vec<Option<libdeflater::Compressor>>> = RefCell::new(Vec::new()));
+
+/// Runs `f` with this thread's libdeflate compressor for `level`
+#[cfg(all(feature = "libdeflate", any(feature = "gzip", feature = "deflate")))]
+fn with_libdeflate_compressor<T, F: FnOnce(&mut libdeflater::Compressor) -> T>(level: u32, f: F) -> T {
+    LIBDEFLATE_COMPRESSORS.with(|compressors| {
+        // Compressors allocate their buffers up front, so one is kept per level and thread
+        let level = level.min(FLATE_MAX_LEVEL) as usize;
+        let mut compressors = compressors.borrow_mut();
+        if compressors.len() <= level {
+            compressors.resize_with(level + 1, || None);
+        }
+        let compressor = compressors[level].get_or_insert_with(|| {
+            let level = libdeflater::CompressionLvl::new(level as i32).unwrap_or_default();
+            libdeflater::Compressor::new(level)
+        });
+        f(compressor)
+    })
+}
+
+/// Compresses a complete buffer using libdeflate, or returns `None` for encodings libdeflate
+/// does not support
+#[cfg(feature = "libdeflate")]
+#[cfg_attr(not(any(feature = "gzip", feature = "deflate")), allow(unused_variables))]
+fn encode_with_libdeflate(encoding: CompressionEncoding, level: u32, data: &[u8]) -> Option<io::Result<Vec<u8>>> {
+    match encoding {
+        #[cfg(feature = "brotli")]
+        CompressionEncoding::Brotli => None,
+        #[cfg(feature = "deflate")]
+        CompressionEncoding::Deflate => Some(deflate_with_libdeflate(level, data)),
+        #[cfg(feature = "gzip")]
+        CompressionEncoding::Gzip => Some(encode_gzip(level, &GzipHeader::default(), data)),
+        #[cfg(feature = "zstd")]
+        CompressionEncoding::Zstd => None,
+    }
+}
+
+#[cfg(all(feature = "libdeflate", feature = "deflate"))]
+fn deflate_with_libdeflate(level: u32, data: &[u8]) -> io::Result<Vec<u8>> {
+    with_libdeflate_compressor(level, |compressor| {
+        let mut compressed = vec![0; compressor.deflate_compress_bound(data.len())];
+        let length = compressor
+            .deflate_compress(data, &mut compressed)
+            .map_err(|err| io::Error::new(io::ErrorKind::Other, err))?;
+        compressed.truncate(length);
+        Ok(compressed)
+    })
+}
+
+/// Whether `encode` compresses buffers of this encoding in one go, rather than streaming them
+/// through an `Encoder`
+pub fn supports_one_shot(encoding: CompressionEncoding) -> bool {
+    match encoding {
+        #[cfg(feature = "brotli")]
+        CompressionEncoding::Brotli => false,
+        #[cfg(feature = "deflate")]
+        CompressionEncoding::Deflate => cfg!(feature = "libdeflate"),
+        #[cfg(feature = "gzip")]
+        CompressionEncoding::Gzip => cfg!(feature = "libdeflate"),
+        #[cfg(feature = "zstd")]
+        CompressionEncoding::Zstd => false,
+    }
+}
+
+/// Compresses a complete buffer into a gzip stream with the given header in one go
+#[cfg(feature = "gzip")]
+pub fn encode_gzip(level: u32, header: &GzipHeader, data: &[u8]) -> io::Result<Vec<u8>> {
+    #[cfg(feature = "libdeflate")]
+    {
+        // libdeflate writes a fixed header, so the raw deflate stream is wrapped here
+        let mut compressed = header.to_bytes();
+        let header_length = compressed.len();
+        let deflated = with_libdeflate_compressor(level, |compressor| {
+            compressed.resize(header_length + compressor.deflate_compress_bound(data.len()), 0);
+            compressor.deflate_compress(data, &mut compressed[header_length..])
+        });
+        let length = deflated.map_err(|err| io::Error::new(io::ErrorKind::Other, err))?;
+        compressed.truncate(header_length + length);
+
+        let mut crc = libdeflater::Crc::new();
+        crc.update(data);
+        let (crc, size) = (crc.sum(), data.len() as u32);
+        compressed.extend_from_slice(&[crc as u8, (crc >> 8) as u8, (crc >> 16) as u8, (crc >> 24) as u8]);
+        compressed.extend_from_slice(&[size as u8, (size >> 8) as u8, (size >> 16) as u8, (size >> 24) as u8]);
+        Ok(compressed)
+    }
+
+    #[cfg(not(feature = "libdeflate"))]
+    {
+        let mut encoder = Encoder::gzip(level, header, Vec::new())?;
+        encoder.write_all(data)?;
+        encoder.finish()
+    }
+}
+
+/// Compresses a complete buffer in one go
+pub fn encode(encoding: CompressionEncoding, data: &[u8]) -> io::Result<Vec<u8>> {
+    encode_with_level(encoding, default_level(encoding), data)
+}
+
+/// Compresses a complete buffer in one go using the given compression level
+pub fn encode_with_level(encoding: CompressionEncoding, level: u32, data: &[u8]) -> io::Result<Vec<u8>> {
+    #[cfg(feature = "libdeflate")]
+    {
+        if let Some(compressed) = encode_with_libdeflate(encoding, level, data) {
+            return compressed;
+        }
+    }
+
+    let mut encoder = Encoder::with_level(encoding, level, Vec::new())?;
+    encoder.write_all(data)?;
+    encoder.finish()
+}
@@ -0,0 +1,451 @@
+//! The compression pipeline shared by the iron middleware and the hyper adapter: rules,
+//! negotiation of a content-, transfer- or transcoding, the concurrency limit, the level, how
+//! the body is encoded and the headers of the finished response.
+
+use std::io;
+use std::io::Write;
+use std::sync::Arc;
+use hyper::header::{ContentEncoding, ContentLength, Encoding, Headers, TransferEncoding};
+
+#[cfg(feature = "gzip")]
+use codec::{encode_gzip, GzipHeader};
+use codec::{encode_with_level, supports_one_shot, Encoder};
+use config::Config;
+use debug;
+#[cfg(feature = "digest")]
+use digest;
+use level::fixed_level;
+use limit::{ConcurrencyLimit, Permit, SaturationPolicy};
+use negotiation::{encoding_of, get_header, negotiate_exchange, negotiate_transcoding, negotiate_transfer_coding, CompressionEncoding, Decision, Exchange, SkipReason};
+#[cfg(feature = "parallel")]
+use parallel::{ParallelGzip, ParallelGzipEncoder};
+#[cfg(feature = "parallel")]
+use pool::ThreadPool;
+use rules::{Matcher, Rule};
+use stats::Stats;
+
+/// The header of a digest over the body as it is sent, which is wrong once the body is encoded
+/// or decoded
+pub(crate) const CONTENT_DIGEST_HEADER: &str = "Content-Digest";
+
+/// How a response is compressed
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub(crate) enum Coding {
+    /// As content-coding, the response is not encoded yet
+    Content,
+    /// As transfer-coding
+    Transfer,
+    /// As content-coding, after decoding the response that was encoded using the given encoding.
+    /// Responses that are not compressed again are sent decoded.
+    Transcoded(CompressionEncoding),
+}
+
+/// What an adapter knows about a request and its response
+pub(crate) struct Facts<'a> {
+    pub exchange: Exchange<'a>,
+    /// The raw lines of the `TE` request header, if sent
+    pub te: Option<&'a [Vec<u8>]>,
+    /// The `Content-Encoding` of the response, if set
+    pub content_encoding: Option<&'a [Encoding]>,
+    /// The segments of the request path, matched against path rules
+    pub path: Vec<&'a str>,
+    /// The id of the route, matched against route rules
+    pub route: Option<&'a str>,
+    /// The name of the adapter-specific predicate that ruled the response out, if any
+    pub excluded_by: Option<&'static str>,
+}
+
+/// What to do with a response
+pub(crate) enum Plan {
+    /// Compress using `encoding` and `level`. The response holds `permit` until it is written.
+    Compress { encoding: CompressionEncoding, coding: Coding, level: u32, permit: Option<Permit> },
+    /// Send the response uncompressed, decoded if it was transcoded
+    Skip { reason: SkipReason, coding: Coding },
+}
+
+/// How the body of a response planned to be compressed is encoded: in parallel for large gzip
+/// bodies, in one go for smaller complete bodies, or while it is written otherwise
+pub(crate) struct BodyEncoding {
+    encoding: CompressionEncoding,
+    level: u32,
+    #[cfg(feature = "gzip")]
+    header: GzipHeader,
+    #[cfg(feature = "parallel")]
+    parallel: Option<(Arc<ThreadPool>, ParallelGzip)>,
+    /// The length of bodies that are compressed in one go if they are written in a single call
+    pub one_shot_length: Option<u64>,
+}
+
+impl BodyEncoding {
+    /// Compresses a complete body
+    pub fn encode(&self, data: &[u8]) -> io::Result<Vec<u8>> {
+        if self.is_parallel() {
+            let mut encoder = self.encoder(Vec::with_capacity(data.len() / 2))?;
+            encoder.write_all(data)?;
+            return encoder.finish();
+        }
+        self.encode_serial(data)
+    }
+
+    /// Creates an encoder writing the compressed body into `inner`
+    #[cfg(feature = "parallel")]
+    pub fn encoder<W: Write>(&self, inner: W) -> io::Result<BodyEncoder<W>> {
+        match self.parallel {
+            Some((ref pool, ref parallel)) => {
+                ParallelGzipEncoder::new(inner, pool.clone(), parallel, self.level, &self.header).map(BodyEncoder::Parallel)
+            }
+            None => self.serial_encoder(inner).map(BodyEncoder::Serial),
+        }
+    }
+
+    #[cfg(not(feature = "parallel"))]
+    pub fn encoder<W: Write>(&self, inner: W) -> io::Result<BodyEncoder<W>> {
+        self.serial_encoder(inner).map(BodyEncoder::Serial)
+    }
+
+    #[cfg(feature = "parallel")]
+    fn is_parallel(&self) -> bool {
+        self.parallel.is_some()
+    }
+
+    #[cfg(not(feature = "parallel"))]
+    fn is_parallel(&self) -> bool {
+        false
+    }
+
+    #[cfg(feature = "gzip")]
+    fn encode_serial(&self, data: &[u8]) -> io::Result<Vec<u8>> {
+        if self.encoding == CompressionEncoding::Gzip {
+            encode_gzip(self.level, &self.header, data)
+        } else {
+            encode_with_level(self.encoding, self.level, data)
+        }
+    }
+
+    #[cfg(not(feature = "gzip"))]
+    fn encode_serial(&self, data: &[u8]) -> io::Result<Vec<u8>> {
+        encode_with_level(self.encoding, self.level, data)
+    }
+
+    #[cfg(feature = "gzip")]
+    fn serial_encoder<W: Write>(&self, inner: W) -> io::Result<Encoder<W>> {
+        if self.encoding == CompressionEncoding::Gzip {
+            Encoder::gzip(self.level, &self.header, inner)
+        } else {
+            Encoder::with_level(self.encoding, self.level, inner)
+        }
+    }
+
+    #[cfg(not(feature = "gzip"))]
+    fn serial_encoder<W: Write>(&self, inner: W) -> io::Result<Encoder<W>> {
+        Encoder::with_level(self.encoding, self.level, inner)
+    }
+}
+
+/// An encoder created by `BodyEncoding`
+pub(crate) enum BodyEncoder<W: Write> {
+    Serial(Encoder<W>),
+    #[cfg(feature = "parallel")]
+    Parallel(ParallelGzipEncoder<W>),
+}
+
+impl<W: Write> BodyEncoder<W> {
+    /// Completes the compressed stream and returns the wrapped writer
+    pub fn finish(self) -> io::Result<W> {
+        match self {
+            BodyEncoder::Serial(encoder) => encoder.finish(),
+            #[cfg(feature = "parallel")]
+            BodyEncoder::Parallel(encoder) => encoder.finish(),
+        }
+    }
+}
+
+impl<W: Write> Write for BodyEncoder<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match *self {
+            BodyEncoder::Serial(ref mut encoder) => encoder.write(buf),
+            #[cfg(feature = "parallel")]
+            BodyEncoder::Parallel(ref mut encoder) => encoder.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match *self {
+            BodyEncoder::Serial(ref mut encoder) => encoder.flush(),
+            #[cfg(feature = "parallel")]
+            BodyEncoder::Parallel(ref mut encoder) => encoder.flush(),
+        }
+    }
+}
+
+/// A rule along with the configuration to negotiate the requests it matches with
+struct MatchedRule {
+    matcher: Matcher,
+    rule: Rule,
+    config: Option<Config>,
+}
+
+/// **Compressor**
+///
+/// The configuration, rules, concurrency limit and counters behind `CompressionMiddleware`.
+/// Create one to compress the responses of plain hyper services, see `hyper_adapter`.
+///
+/// # Example
+/// ```rust
+/// use iron_pack::{Compressor, Config, Rule};
+///
+/// let compressor = Compressor::with_config(Config::default().min_bytes(1024))
+///     .rule("/api/stream/**", Rule::never());
+/// ```
+pub struct Compressor {
+    pub(crate) config: Config,
+    pub(crate) stats: Stats,
+    limit: Option<Arc<ConcurrencyLimit>>,
+    rules: Vec<MatchedRule>,
+    #[cfg(feature = "parallel")]
+    pool: Option<Arc<ThreadPool>>,
+}
+
+impl Compressor {
+    /// Creates a compressor using the default configuration
+    pub fn new() -> Compressor {
+        Compressor::with_config(Config::default())
+    }
+
+    /// Creates a compressor using a custom configuration
+    pub fn with_config(config: Config) -> Compressor {
+        Compressor {
+            limit: config.concurrency_limit.map(|(max, _)| ConcurrencyLimit::new(max)),
+            rules: Vec::new(),
+            stats: Stats::default(),
+            #[cfg(feature = "parallel")]
+            pool: config.parallel_gzip.as_ref().map(|parallel| Arc::new(ThreadPool::new(parallel.threads))),
+            config,
+        }
+    }
+
+    /// Apply `rule` to requests whose URL path matches `pattern`, see
+    /// `CompressionMiddleware::rule`
+    pub fn rule(mut self, pattern: &str, rule: Rule) -> Compressor {
+        self.add_rule(Matcher::path(pattern), rule);
+        self
+    }
+
+    pub(crate) fn add_rule(&mut self, matcher: Matcher, rule: Rule) {
+        let config = rule.encoding.map(|preferred| {
+            let mut priority = vec![preferred];
+            priority.extend(self.config.priority.iter().cloned().filter(|&encoding| encoding != preferred));
+            self.config.clone().priority(priority)
+        });
+        self.rules.push(MatchedRule { matcher, rule, config });
+    }
+
+    /// A handle to the counters of this compressor
+    pub fn stats(&self) -> Stats {
+        self.stats.clone()
+    }
+
+    /// Decides whether and how a response should be compressed, without taking a slot of the
+    /// concurrency limit or counting the response
+    pub(crate) fn decide(&self, facts: &Facts) -> (Decision, Coding, Option<&Rule>) {
+        self.decide_with(facts, |config| negotiate_coding(facts, config))
+    }
+
+    /// Decides which of the given precompressed variants of a response to send, if any. The
+    /// rules, predicates and configuration apply like they do to responses compressed on the
+    /// fly, restricted to the `available` encodings.
+    pub(crate) fn decide_among(&self, facts: &Facts, available: &[CompressionEncoding]) -> Decision {
+        self.decide_with(facts, |config| {
+            // Precompressed variants carry no random padding
+            if config.breach.pads() {
+                return (Decision::Skip(SkipReason::Padding), Coding::Content);
+            }
+            let priority = config.priority.iter().cloned().filter(|encoding| available.contains(encoding)).collect();
+            (negotiate_exchange(&facts.exchange, &config.clone().priority(priority)), Coding::Content)
+        }).0
+    }
+
+    /// Applies the predicates and the first matching rule, and negotiates with the configuration
+    /// of that rule otherwise
+    fn decide_with<F>(&self, facts: &Facts, negotiate: F) -> (Decision, Coding, Option<&Rule>)
+        where F: Fn(&Config) -> (Decision, Coding) {
+        let matched = self.rules.iter().find(|matched| matched.matcher.matches(&facts.path, facts.route));
+        let rule = matched.map(|matched| &matched.rule);
+        let (decision, coding) = match (facts.excluded_by, matched) {
+            (Some(name), _) => (Decision::Skip(SkipReason::Predicate(name)), Coding::Content),
+            (None, Some(&MatchedRule { rule: Rule { compress: false, .. }, .. })) => (Decision::Skip(SkipReason::Rule), Coding::Content),
+            (None, Some(&MatchedRule { config: Some(ref config), .. })) => negotiate(config),
+            (None, _) => negotiate(&self.config),
+        };
+        (decision, coding, rule)
+    }
+
+    /// Decides what to do with a response, takes a slot of the concurrency limit for responses
+    /// to compress and counts the outcome
+    pub(crate) fn plan(&self, facts: &Facts) -> Plan {
+        let plan = match self.decide(facts) {
+            (Decision::Compress(encoding), coding, rule) => match self.acquire_permit() {
+                Ok(permit) => {
+                    let content_length = match coding {
+                        Coding::Transcoded(_) => None,
+                        _ => facts.exchange.content_length,
+                    };
+                    let level = self.level(encoding, content_length, rule);
+                    Plan::Compress { encoding, coding, level, permit }
+                }
+                Err(reason) => Plan::Skip { reason, coding },
+            },
+            (Decision::Skip(reason), coding, _) => Plan::Skip { reason, coding },
+        };
+
+        match plan {
+            Plan::Compress { .. } => self.stats.record_compressed(),
+            Plan::Skip { ref reason, .. } => self.stats.record_skipped(reason),
+        }
+        plan
+    }
+
+    /// The level to compress a response with
+    fn level(&self, encoding: CompressionEncoding, content_length: Option<u64>, rule: Option<&Rule>) -> u32 {
+        if let Some(level) = rule.and_then(|rule| rule.level) {
+            return level.of(encoding);
+        }
+        match self.config.adaptive_level {
+            Some(ref policy) if !self.config.is_oversize(content_length) => {
+                policy.level(encoding, content_length, self.stats.in_flight(), self.stats.throughput())
+            }
+            _ => fixed_level(&self.config, encoding, content_length),
+        }
+    }
+
+    /// Takes a slot of the concurrency limit, if one is configured
+    fn acquire_permit(&self) -> Result<Option<Permit>, SkipReason> {
+        let (limit, policy) = match (self.limit.as_ref(), self.config.concurrency_limit) {
+            (Some(limit), Some((_, policy))) => (limit, policy),
+            _ => return Ok(None),
+        };
+        if let Some(permit) = ConcurrencyLimit::try_acquire(limit) {
+            return Ok(Some(permit));
+        }
+
+        match policy {
+            SaturationPolicy::Identity => {
+                self.stats.record_saturated();
+                Err(SkipReason::Saturated)
+            }
+            SaturationPolicy::Queue(timeout) => {
+                self.stats.record_queued();
+                match ConcurrencyLimit::acquire_timeout(limit, timeout) {
+                    Some(permit) => Ok(Some(permit)),
+                    None => {
+                        self.stats.record_saturated();
+                        Err(SkipReason::Saturated)
+                    }
+                }
+            }
+        }
+    }
+
+    /// How to encode the body of a response planned to be compressed using `encoding` and
+    /// `level`. Pass the length of the body as it is encoded, if it is known.
+    pub(crate) fn body_encoding(&self, encoding: CompressionEncoding, level: u32, content_length: Option<u64>) -> BodyEncoding {
+        let mut body_encoding = BodyEncoding {
+            encoding,
+            level,
+            #[cfg(feature = "gzip")]
+            header: self.config.response_gzip_header(),
+            #[cfg(feature = "parallel")]
+            parallel: self.parallel_gzip(encoding, content_length),
+            one_shot_length: None,
+        };
+        body_encoding.one_shot_length = match content_length {
+            Some(length) if !body_encoding.is_parallel() && length <= self.config.one_shot_limit && supports_one_shot(encoding) => Some(length),
+            _ => None,
+        };
+        body_encoding
+    }
+
+    /// The pool and configuration to compress a gzip body of this length in parallel, if it is
+    /// large enough
+    #[cfg(feature = "parallel")]
+    fn parallel_gzip(&self, encoding: CompressionEncoding, content_length: Option<u64>) -> Option<(Arc<ThreadPool>, ParallelGzip)> {
+        match (self.pool.as_ref(), self.config.parallel_gzip.as_ref(), content_length) {
+            (Some(pool), Some(parallel), Some(length)) if encoding == CompressionEncoding::Gzip && length >= parallel.min_bytes => {
+                Some((pool.clone(), parallel.clone()))
+            }
+            _ => None,
+        }
+    }
+
+    /// Sets the headers of a response once its plan is carried out: the coding headers and the
+    /// debug header, and for bodies `encoded` into memory their `Content-Length` and digests.
+    /// `bytes_in` is the length of the body before it is compressed, if known.
+    pub(crate) fn finish(&self, request_headers: &Headers, decision: &Decision, coding: Coding, bytes_in: Option<u64>, encoded: Option<&[u8]>, response_headers: &mut Headers) {
+        let debug_value = match *decision {
+            Decision::Compress(encoding) => {
+                if coding == Coding::Transfer {
+                    response_headers.set(TransferEncoding(vec![get_header(&encoding)]));
+                } else {
+                    response_headers.set(ContentEncoding(vec![get_header(&encoding)]));
+                    response_headers.remove_raw(CONTENT_DIGEST_HEADER);
+                }
+                response_headers.remove::<ContentLength>();
+
+                if let Some(encoded) = encoded {
+                    // hyper only adds the chunked transfer-coding to bodies of unknown length
+                    if coding != Coding::Transfer {
+                        response_headers.set(ContentLength(encoded.len() as u64));
+                        self.set_digests(response_headers, encoded);
+                    }
+                }
+                debug::compressed(&encoding, bytes_in, encoded.map(|encoded| encoded.len() as u64))
+            }
+            Decision::Skip(ref reason) => {
+                if let Coding::Transcoded(_) = coding {
+                    response_headers.remove::<ContentEncoding>();
+                    response_headers.remove::<ContentLength>();
+                    response_headers.remove_raw(CONTENT_DIGEST_HEADER);
+                }
+                debug::skipped(reason)
+            }
+        };
+        if debug::is_requested(request_headers, &self.config) {
+            debug::set(response_headers, debug_value);
+        }
+    }
+
+    #[cfg(feature = "digest")]
+    fn set_digests(&self, response_headers: &mut Headers, encoded: &[u8]) {
+        if self.config.has_digests() {
+            digest::set(response_headers, &self.config.digests, encoded);
+        }
+    }
+
+    #[cfg(not(feature = "digest"))]
+    fn set_digests(&self, _: &mut Headers, _: &[u8]) {}
+}
+
+impl Default for Compressor {
+    fn default() -> Compressor {
+        Compressor::new()
+    }
+}
+
+/// Negotiates a transfer-coding first, if enabled, and a content-coding otherwise. Responses
+/// the handler already encoded are transcoded, if enabled.
+fn negotiate_coding(facts: &Facts, config: &Config) -> (Decision, Coding) {
+    if config.transfer_coding {
+        if let decision @ Decision::Compress(_) = negotiate_transfer_coding(facts.te, &facts.exchange, config) {
+            return (decision, Coding::Transfer);
+        }
+    }
+    let decision = negotiate_exchange(&facts.exchange, config);
+    if config.transcode && decision == Decision::Skip(SkipReason::AlreadyEncoded) {
+        if let Some(from) = facts.content_encoding.and_then(encoding_of) {
+            if let Some(transcoded) = negotiate_transcoding(from, &facts.exchange, config) {
+                return (transcoded, Coding::Transcoded(from));
+            }
+        }
+    }
+    (decision, Coding::Content)
+}
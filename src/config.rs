@@ -0,0 +1,272 @@
+//! Configuration of the compression negotiation.
+
+use breach::BreachMitigation;
+#[cfg(feature = "digest")]
+use digest::DigestAlgorithm;
+#[cfg(feature = "padding")]
+use breach;
+#[cfg(feature = "gzip")]
+use codec::GzipHeader;
+use level::AdaptiveLevel;
+use limit::SaturationPolicy;
+use negotiation::{CompressionEncoding, Strategy};
+#[cfg(feature = "parallel")]
+use parallel::ParallelGzip;
+
+const DEFAULT_MIN_BYTES_FOR_COMPRESSION: u64 = 860;
+#[cfg(feature = "libdeflate")]
+const DEFAULT_ONE_SHOT_LIMIT: u64 = 1024 * 1024;
+
+/// What happens to responses with a `Content-Length` above `Config::max_bytes`
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum OversizePolicy {
+    /// Send the response uncompressed
+    Skip,
+    /// Compress the response using the fastest level of its encoding
+    Fastest,
+}
+
+/// **Compression Configuration**
+///
+/// Controls which responses are compressed and which encodings are preferred. The defaults are
+/// the ones `CompressionMiddleware` uses.
+///
+/// # Example
+/// ```rust
+/// use iron_pack::{Config, CompressionEncoding};
+///
+/// # #[cfg(all(feature = "gzip", feature = "brotli"))]
+/// # fn main() {
+/// let config = Config::default()
+///     .min_bytes(1024)
+///     .priority(vec![CompressionEncoding::Gzip, CompressionEncoding::Brotli]);
+/// # }
+/// # #[cfg(not(all(feature = "gzip", feature = "brotli")))]
+/// # fn main() {}
+/// ```
+#[derive(Clone, Debug)]
+pub struct Config {
+    pub(crate) min_bytes: u64,
+    pub(crate) max_bytes: Option<(u64, OversizePolicy)>,
+    pub(crate) priority: Vec<CompressionEncoding>,
+    pub(crate) strategy: Strategy,
+    pub(crate) transfer_coding: bool,
+    pub(crate) transcode: bool,
+    pub(crate) debug_header: bool,
+    pub(crate) debug_token: Option<(String, String)>,
+    #[cfg(feature = "digest")]
+    pub(crate) digests: Vec<DigestAlgorithm>,
+    pub(crate) one_shot_limit: u64,
+    pub(crate) concurrency_limit: Option<(usize, SaturationPolicy)>,
+    pub(crate) adaptive_level: Option<AdaptiveLevel>,
+    pub(crate) breach: BreachMitigation,
+    #[cfg(feature = "gzip")]
+    pub(crate) gzip_header: GzipHeader,
+    #[cfg(feature = "parallel")]
+    pub(crate) parallel_gzip: Option<ParallelGzip>,
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            min_bytes: DEFAULT_MIN_BYTES_FOR_COMPRESSION,
+            max_bytes: None,
+            priority: CompressionEncoding::all(),
+            strategy: Strategy::default(),
+            transfer_coding: false,
+            transcode: false,
+            debug_header: false,
+            debug_token: None,
+            #[cfg(feature = "digest")]
+            digests: Vec::new(),
+            #[cfg(feature = "libdeflate")]
+            one_shot_limit: DEFAULT_ONE_SHOT_LIMIT,
+            #[cfg(not(feature = "libdeflate"))]
+            one_shot_limit: 0,
+            concurrency_limit: None,
+            adaptive_level: None,
+            breach: BreachMitigation::default(),
+            #[cfg(feature = "gzip")]
+            gzip_header: GzipHeader::default(),
+            #[cfg(feature = "parallel")]
+            parallel_gzip: None,
+        }
+    }
+}
+
+impl Config {
+    /// Responses with a `Content-Length` below this value are not compressed (default: 860)
+    pub fn min_bytes(mut self, min_bytes: u64) -> Config {
+        self.min_bytes = min_bytes;
+        self
+    }
+
+    /// Responses with a `Content-Length` above this value are handled according to `policy`
+    /// (default: no limit)
+    ///
+    /// There is no cutoff in the middle of a body: its `Content-Encoding` is sent before the
+    /// body, and the encoders can't change their level once they started. Responses without a
+    /// `Content-Length` are skipped regardless of this limit, as `SkipReason::UnknownLength`.
+    pub fn max_bytes(mut self, max_bytes: u64, policy: OversizePolicy) -> Config {
+        self.max_bytes = Some((max_bytes, policy));
+        self
+    }
+
+    /// Whether a response of this length exceeds `max_bytes` and should be compressed using the
+    /// fastest level
+    pub(crate) fn is_oversize(&self, content_length: Option<u64>) -> bool {
+        match (self.max_bytes, content_length) {
+            (Some((max_bytes, OversizePolicy::Fastest)), Some(length)) => length > max_bytes,
+            _ => false,
+        }
+    }
+
+    /// The order in which encodings are chosen when the client accepts several of them with
+    /// the same quality (default: brotli, gzip, deflate, as far as they are enabled)
+    pub fn priority(mut self, priority: Vec<CompressionEncoding>) -> Config {
+        self.priority = priority;
+        self
+    }
+
+    /// How the encoding is chosen among the ones the client accepts (default:
+    /// `Strategy::ClientQuality`)
+    pub fn strategy(mut self, strategy: Strategy) -> Config {
+        self.strategy = strategy;
+        self
+    }
+
+    /// Negotiate on the `TE` request header first, and compress the response as
+    /// gzip or deflate transfer-coding, layered with chunked (default: false)
+    ///
+    /// Transfer-codings are hop-by-hop, so the representation, its `ETag` and its digests stay
+    /// unchanged. Requests without an acceptable transfer-coding fall back to `Accept-Encoding`.
+    pub fn transfer_coding(mut self, enabled: bool) -> Config {
+        self.transfer_coding = enabled;
+        self
+    }
+
+    /// Decode responses the handler already encoded using a coding the client does not accept,
+    /// and compress them again using one it accepts, or send them decoded (default: false)
+    ///
+    /// Only responses with a single gzip, deflate or brotli `Content-Encoding` are transcoded.
+    /// Their encoded body is read into memory to decode it. `min_bytes` and `max_bytes` apply to
+    /// the encoded length, smaller responses are sent decoded.
+    pub fn transcode(mut self, enabled: bool) -> Config {
+        self.transcode = enabled;
+        self
+    }
+
+    /// Attach an `X-Compression` header to every response that explains the compression
+    /// decision, e.g. `br; in=10240; out=1830` or `skipped; reason=too-small` (default: false)
+    ///
+    /// The header does not change how responses are sent. `in` is only reported for responses
+    /// with a known length. `out` is only reported for responses compressed into memory before
+    /// they are sent, which are the ones that get digests and the ones of `hyper_adapter::send`.
+    /// Other responses are compressed while they are written, after the headers are sent.
+    pub fn debug_header(mut self, enabled: bool) -> Config {
+        self.debug_header = enabled;
+        self
+    }
+
+    /// Only attach the `X-Compression` header to requests that send `header` with the value
+    /// `token`. Enables the debug header.
+    pub fn debug_token<H: Into<String>, T: Into<String>>(mut self, header: H, token: T) -> Config {
+        self.debug_header = true;
+        self.debug_token = Some((header.into(), token.into()));
+        self
+    }
+
+    /// Attach `Content-Digest` and `Repr-Digest` headers using these algorithms to compressed
+    /// responses (default: none)
+    ///
+    /// The content-coding is part of the representation, so `Repr-Digest` covers the compressed
+    /// body like `Content-Digest` does (RFC 9530). Compressed responses are buffered in this mode
+    /// to compute the digests before the headers are sent. Transfer-coded responses and responses
+    /// streamed through `hyper_adapter::start` never get digests.
+    ///
+    /// A `Content-Digest` set by the handler is removed from every response whose body is
+    /// encoded or decoded, whether digests are configured or not.
+    #[cfg(feature = "digest")]
+    pub fn digests(mut self, algorithms: Vec<DigestAlgorithm>) -> Config {
+        self.digests = algorithms;
+        self
+    }
+
+    /// Whether compressed responses get digest headers
+    #[cfg(feature = "digest")]
+    pub(crate) fn has_digests(&self) -> bool {
+        !self.digests.is_empty()
+    }
+
+    #[cfg(not(feature = "digest"))]
+    pub(crate) fn has_digests(&self) -> bool {
+        false
+    }
+
+    /// Responses with a `Content-Length` up to this value are compressed in one go using
+    /// libdeflate, if the chosen encoding supports it (default: 1 MiB)
+    ///
+    /// Only bodies that write their whole length in a single call, like `Vec<u8>` and `String`
+    /// bodies, are compressed in one go. Files and other bodies written in chunks are streamed,
+    /// so nothing is read into memory for this.
+    #[cfg(feature = "libdeflate")]
+    pub fn one_shot_limit(mut self, limit: u64) -> Config {
+        self.one_shot_limit = limit;
+        self
+    }
+
+    /// Compress at most `max` responses at the same time, e.g. the number of cores that may be
+    /// spent on compression. `policy` decides what happens to further responses (default:
+    /// unlimited)
+    ///
+    /// A streamed response keeps its slot until its body is written.
+    pub fn concurrency_limit(mut self, max: usize, policy: SaturationPolicy) -> Config {
+        self.concurrency_limit = Some((max, policy));
+        self
+    }
+
+    /// Pick the compression level of each response depending on its size and the load of the
+    /// server (default: disabled, every encoding uses a fixed level)
+    pub fn adaptive_level(mut self, policy: AdaptiveLevel) -> Config {
+        self.adaptive_level = Some(policy);
+        self
+    }
+
+    /// Protect responses against the BREACH attack (default: no mitigation)
+    pub fn breach_mitigation(mut self, mitigation: BreachMitigation) -> Config {
+        self.breach = mitigation;
+        self
+    }
+
+    /// The header fields of gzip responses (default: no modification time, unknown OS and no
+    /// optional fields, so identical bodies are compressed into identical responses)
+    #[cfg(feature = "gzip")]
+    pub fn gzip_header(mut self, header: GzipHeader) -> Config {
+        self.gzip_header = header;
+        self
+    }
+
+    /// The gzip header of a single response. BREACH padding replaces the comment field.
+    #[cfg(feature = "gzip")]
+    pub(crate) fn response_gzip_header(&self) -> GzipHeader {
+        #[cfg_attr(not(feature = "padding"), allow(unused_mut))]
+        let mut header = self.gzip_header.clone();
+        #[cfg(feature = "padding")]
+        {
+            if self.breach.pads() {
+                header.comment = Some(breach::padding(self.breach.max_padding));
+            }
+        }
+        header
+    }
+
+    /// Compress large gzip responses in blocks on a pool of worker threads (default: disabled)
+    ///
+    /// The output is a single regular gzip stream. Compressing blocks independently costs a few
+    /// percent of compression ratio, so this only pays off for bodies of several megabytes.
+    #[cfg(feature = "parallel")]
+    pub fn parallel_gzip(mut self, parallel_gzip: ParallelGzip) -> Config {
+        self.parallel_gzip = Some(parallel_gzip);
+        self
+    }
+}
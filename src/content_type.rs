@@ -0,0 +1,29 @@
+//! Which content types are worth compressing.
+
+use hyper::mime::Mime;
+
+const COMPRESSIBLE_APPLICATION: [&str; 9] = [
+    "json", "javascript", "ecmascript", "x-javascript", "xml", "x-www-form-urlencoded", "wasm", "vnd.ms-fontobject", "x-font-ttf",
+];
+const COMPRESSIBLE_IMAGE: [&str; 3] = ["bmp", "x-icon", "vnd.microsoft.icon"];
+const COMPRESSIBLE_FONT: [&str; 3] = ["ttf", "otf", "collection"];
+
+/// Whether content of this type is usually worth compressing: text, JSON, JavaScript, XML, SVG,
+/// WebAssembly and uncompressed fonts. Images, audio, video, compressed fonts and archives are
+/// compressed already.
+pub fn is_compressible(mime: &Mime) -> bool {
+    let Mime(ref top, ref sub, _) = *mime;
+    let sub: &str = sub;
+    match &**top {
+        "text" => true,
+        "application" => is_structured_text(sub) || COMPRESSIBLE_APPLICATION.contains(&sub),
+        "image" => is_structured_text(sub) || COMPRESSIBLE_IMAGE.contains(&sub),
+        "font" => COMPRESSIBLE_FONT.contains(&sub),
+        _ => false,
+    }
+}
+
+/// JSON and XML based types like `application/ld+json` or `image/svg+xml`
+fn is_structured_text(sub: &str) -> bool {
+    sub.ends_with("+json") || sub.ends_with("+xml")
+}
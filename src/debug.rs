@@ -0,0 +1,44 @@
+//! The `X-Compression` diagnostic header.
+
+use hyper::header::Headers;
+
+use config::Config;
+use negotiation::{CompressionEncoding, SkipReason};
+
+pub const DEBUG_HEADER: &str = "X-Compression";
+
+/// Whether the diagnostic header should be attached to the response of a request
+pub fn is_requested(request_headers: &Headers, config: &Config) -> bool {
+    if !config.debug_header {
+        return false;
+    }
+    match config.debug_token {
+        Some((ref header, ref token)) => {
+            request_headers
+                .get_raw(header)
+                .map_or(false, |values| values.iter().any(|value| value == token.as_bytes()))
+        }
+        None => true,
+    }
+}
+
+/// Describes a compressed response. Streamed responses are described before they are
+/// compressed, so their compressed size is unknown.
+pub fn compressed(encoding: &CompressionEncoding, bytes_in: Option<u64>, bytes_out: Option<u64>) -> String {
+    let mut value = encoding.to_string();
+    if let Some(bytes_in) = bytes_in {
+        value.push_str(&format!("; in={}", bytes_in));
+    }
+    if let Some(bytes_out) = bytes_out {
+        value.push_str(&format!("; out={}", bytes_out));
+    }
+    value
+}
+
+pub fn skipped(reason: &SkipReason) -> String {
+    format!("skipped; reason={}", reason)
+}
+
+pub fn set(response_headers: &mut Headers, value: String) {
+    response_headers.set_raw(DEBUG_HEADER, vec![value.into_bytes()]);
+}
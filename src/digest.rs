@@ -0,0 +1,96 @@
+//! `Content-Digest` and `Repr-Digest` integrity headers of compressed responses.
+//!
+//! Iron can't send trailers, so the digests are only added to responses that are buffered
+//! before they are sent. Streamed responses, like the ones of `hyper_adapter::start`, never get
+//! digests.
+
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use hyper::header::Headers;
+use sha2::{Digest, Sha256, Sha512};
+
+use compressor::CONTENT_DIGEST_HEADER;
+
+pub const REPR_DIGEST_HEADER: &str = "Repr-Digest";
+
+/// A hash algorithm of the `Content-Digest` and `Repr-Digest` headers
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum DigestAlgorithm {
+    /// SHA-256, `sha-256`
+    Sha256,
+    /// SHA-512, `sha-512`
+    Sha512,
+}
+
+impl DigestAlgorithm {
+    fn key(&self) -> &'static str {
+        match *self {
+            DigestAlgorithm::Sha256 => "sha-256",
+            DigestAlgorithm::Sha512 => "sha-512",
+        }
+    }
+}
+
+#[derive(Clone)]
+enum Hasher {
+    Sha256(Sha256),
+    Sha512(Sha512),
+}
+
+/// Hashes data with several algorithms at once
+#[derive(Clone)]
+pub struct Hashers(Vec<(DigestAlgorithm, Hasher)>);
+
+impl Hashers {
+    pub fn new(algorithms: &[DigestAlgorithm]) -> Hashers {
+        Hashers(algorithms
+            .iter()
+            .map(|&algorithm| {
+                let hasher = match algorithm {
+                    DigestAlgorithm::Sha256 => Hasher::Sha256(Sha256::new()),
+                    DigestAlgorithm::Sha512 => Hasher::Sha512(Sha512::new()),
+                };
+                (algorithm, hasher)
+            })
+            .collect())
+    }
+
+    pub fn of(algorithms: &[DigestAlgorithm], data: &[u8]) -> Hashers {
+        let mut hashers = Hashers::new(algorithms);
+        hashers.update(data);
+        hashers
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        for &mut (_, ref mut hasher) in &mut self.0 {
+            match *hasher {
+                Hasher::Sha256(ref mut sha) => sha.update(data),
+                Hasher::Sha512(ref mut sha) => sha.update(data),
+            }
+        }
+    }
+
+    /// The digests as structured field dictionary, e.g. `sha-256=:<base64>:`
+    pub fn header_value(&self) -> String {
+        self.0
+            .iter()
+            .map(|&(algorithm, ref hasher)| {
+                let digest = match *hasher {
+                    Hasher::Sha256(ref sha) => sha.clone().finalize().to_vec(),
+                    Hasher::Sha512(ref sha) => sha.clone().finalize().to_vec(),
+                };
+                format!("{}=:{}:", algorithm.key(), BASE64.encode(&digest))
+            })
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
+
+/// Sets `Content-Digest` and `Repr-Digest` of a content-coded body. The content-coding is part
+/// of the representation (RFC 9530), so both digests cover the compressed body of unranged
+/// responses.
+pub fn set(response_headers: &mut Headers, algorithms: &[DigestAlgorithm], content: &[u8]) {
+    let digests = Hashers::of(algorithms, content).header_value().into_bytes();
+    response_headers.set_raw(REPR_DIGEST_HEADER, vec![digests.clone()]);
+    response_headers.set_raw(CONTENT_DIGEST_HEADER, vec![digests]);
+}
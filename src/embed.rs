@@ -0,0 +1,131 @@
+//! Serves assets embedded into the binary with `include_compressed!`.
+
+use std::collections::HashMap;
+use std::io;
+use iron::prelude::*;
+use iron::headers::*;
+use iron::{Handler, status};
+use mime_guess;
+
+use config::Config;
+use middleware::CompressionMiddleware;
+use negotiation::{get_header, CompressionEncoding, Decision};
+
+/// A file embedded at build time along with its precompressed variants, see
+/// `include_compressed!`. Variants that are not smaller than the file are `None`.
+#[derive(Clone, Copy, Debug)]
+pub struct EmbeddedAsset {
+    /// The path the asset was embedded from, its extension decides the content type
+    pub path: &'static str,
+    pub identity: &'static [u8],
+    pub brotli: Option<&'static [u8]>,
+    pub gzip: Option<&'static [u8]>,
+}
+
+impl EmbeddedAsset {
+    /// The precompressed variant using `encoding`, if it was embedded
+    fn variant(&self, encoding: CompressionEncoding) -> Option<&'static [u8]> {
+        match encoding {
+            #[cfg(feature = "brotli")]
+            CompressionEncoding::Brotli => self.brotli,
+            #[cfg(feature = "deflate")]
+            CompressionEncoding::Deflate => None,
+            #[cfg(feature = "gzip")]
+            CompressionEncoding::Gzip => self.gzip,
+            #[cfg(feature = "zstd")]
+            CompressionEncoding::Zstd => None,
+        }
+    }
+}
+
+/// **Embedded Assets**
+///
+/// Serves `EmbeddedAsset`s under URL paths. The precompressed variants are chosen between like
+/// the middleware chooses an encoding: its rules, predicates, strategy, priority and BREACH
+/// mitigation apply. Clients accepting none of them get the identity variant. Requests for a
+/// directory are served using its `index.html`.
+///
+/// # Example
+/// ```rust,ignore
+/// #[macro_use]
+/// extern crate iron_pack;
+/// extern crate iron;
+///
+/// use iron::prelude::*;
+/// use iron_pack::EmbeddedAssets;
+///
+/// fn main() {
+///     let assets = EmbeddedAssets::new()
+///         .asset("/index.html", include_compressed!("dist/index.html"))
+///         .asset("/app.js", include_compressed!("dist/app.js"));
+///     Iron::new(assets).http("localhost:3000").unwrap();
+/// }
+/// ```
+pub struct EmbeddedAssets {
+    assets: HashMap<String, EmbeddedAsset>,
+    middleware: CompressionMiddleware,
+}
+
+impl EmbeddedAssets {
+    /// Chooses between the variants using the default configuration
+    pub fn new() -> EmbeddedAssets {
+        EmbeddedAssets::with_config(Config::default())
+    }
+
+    /// Chooses between the variants using `config`
+    pub fn with_config(config: Config) -> EmbeddedAssets {
+        EmbeddedAssets::with_middleware(CompressionMiddleware::with_config(config))
+    }
+
+    /// Chooses between the variants using `middleware`, including its rules
+    pub fn with_middleware(middleware: CompressionMiddleware) -> EmbeddedAssets {
+        EmbeddedAssets {
+            assets: HashMap::new(),
+            middleware,
+        }
+    }
+
+    /// Serves `asset` under the URL path `path`, e.g. `/app.js`
+    pub fn asset(mut self, path: &str, asset: EmbeddedAsset) -> EmbeddedAssets {
+        self.assets.insert(path.trim_matches('/').to_owned(), asset);
+        self
+    }
+
+    fn lookup(&self, req: &Request) -> Option<&EmbeddedAsset> {
+        let path = req.url.path().join("/");
+        self.assets.get(&path).or_else(|| {
+            let index = format!("{}/index.html", path.trim_matches('/'));
+            self.assets.get(index.trim_matches('/'))
+        })
+    }
+}
+
+impl Default for EmbeddedAssets {
+    fn default() -> EmbeddedAssets {
+        EmbeddedAssets::new()
+    }
+}
+
+impl Handler for EmbeddedAssets {
+    fn handle(&self, req: &mut Request) -> IronResult<Response> {
+        let asset = match self.lookup(req) {
+            Some(asset) => asset,
+            None => return Err(IronError::new(io::Error::new(io::ErrorKind::NotFound, "no such asset"), status::NotFound)),
+        };
+
+        let mime = mime_guess::guess_mime_type(asset.path);
+        let mut res = Response::with((status::Ok, mime.clone(), asset.identity));
+        let available: Vec<CompressionEncoding> = CompressionEncoding::all()
+            .into_iter()
+            .filter(|&encoding| asset.variant(encoding).is_some())
+            .collect();
+        if let Decision::Compress(encoding) = self.middleware.choose_variant(req, &res, &available) {
+            if let Some(data) = asset.variant(encoding) {
+                res = Response::with((status::Ok, mime, data));
+                res.headers.set(ContentEncoding(vec![get_header(&encoding)]));
+            }
+        }
+        res.headers.set_raw("Vary", vec![b"Accept-Encoding".to_vec()]);
+        Ok(res)
+    }
+}
@@ -0,0 +1,222 @@
+//! An adapter for plain hyper services, available with the `hyper` feature.
+//!
+//! hyper handlers write their response themselves, so instead of a middleware this adapter
+//! provides functions to send a compressed response. They run the same pipeline as
+//! `CompressionMiddleware`: rules, transfer-coding, transcoding, the concurrency limit, the
+//! levels, one-shot and parallel compression, and the coding, digest and debug headers of a
+//! `Compressor`. Predicates take iron types and are not available here.
+//!
+//! Mark responses as `Sensitive` by setting it as header.
+//!
+//! # Example
+//! ```rust,no_run
+//! extern crate hyper;
+//! extern crate iron_pack;
+//!
+//! use hyper::server::{Server, Request, Response};
+//! use iron_pack::Compressor;
+//! use iron_pack::hyper_adapter;
+//!
+//! fn main() {
+//!     let compressor = Compressor::new();
+//!     Server::http("localhost:3000").unwrap().handle(move |req: Request, res: Response| {
+//!         let nana = "Na".repeat(5000);
+//!         let body = format!("{}, Batman!", nana);
+//!         hyper_adapter::send(&req, res, body.as_bytes(), &compressor).unwrap();
+//!     }).unwrap();
+//! }
+//! ```
+
+use std::io;
+use std::io::Write;
+use std::time::Instant;
+use hyper::header::{ContentEncoding, ContentLength, Headers};
+use hyper::net::{Fresh, Streaming};
+use hyper::server::{Request, Response};
+use hyper::uri::RequestUri;
+
+use breach::{self, Sensitive};
+use codec::decode;
+use compressor::{BodyEncoder, Coding, Compressor, Facts, Plan};
+use limit::Permit;
+use negotiation::{CompressionEncoding, Decision, Exchange};
+use rules::segments;
+use stats::InFlight;
+
+/// What the pipeline needs to know about a hyper request and its response
+fn facts<'a>(req: &'a Request, response_headers: &'a Headers, content_length: Option<u64>) -> Facts<'a> {
+    let path = match req.uri {
+        RequestUri::AbsolutePath(ref path) => path.as_str(),
+        RequestUri::AbsoluteUri(ref url) => url.path(),
+        _ => "",
+    };
+    Facts {
+        exchange: Exchange {
+            accept_encoding: req.headers.get_raw("Accept-Encoding"),
+            has_body: true,
+            content_encoded: response_headers.has::<ContentEncoding>(),
+            content_length: content_length.or_else(|| response_headers.get::<ContentLength>().map(|&ContentLength(length)| length)),
+            cross_site: breach::is_cross_site(&req.headers),
+            sensitive: response_headers.has::<Sensitive>(),
+        },
+        te: req.headers.get_raw("TE"),
+        content_encoding: response_headers.get::<ContentEncoding>().map(|content_encoding| &content_encoding.0[..]),
+        path: segments(path.split('?').next().unwrap_or("")).collect(),
+        route: None,
+        excluded_by: None,
+    }
+}
+
+/// Decides whether the response to a hyper request should be compressed, and using which
+/// encoding. Pass the length of the body if it is known and not set as `Content-Length` yet.
+pub fn negotiate(req: &Request, res: &Response<Fresh>, content_length: Option<u64>, compressor: &Compressor) -> Decision {
+    compressor.decide(&facts(req, res.headers(), content_length)).0
+}
+
+/// Sends `body` as response, compressed if the client accepts it
+pub fn send(req: &Request, mut res: Response<Fresh>, body: &[u8], compressor: &Compressor) -> io::Result<()> {
+    let plan = compressor.plan(&facts(req, res.headers(), Some(body.len() as u64)));
+    res.headers_mut().remove::<Sensitive>();
+
+    match plan {
+        Plan::Compress { encoding, coding, level, permit: _permit } => {
+            let decoded;
+            let body = match coding {
+                Coding::Transcoded(from) => {
+                    decoded = decode(from, body)?;
+                    &decoded[..]
+                }
+                _ => body,
+            };
+
+            let _in_flight = compressor.stats.start_compression();
+            let start = Instant::now();
+            let compressed = compressor.body_encoding(encoding, level, Some(body.len() as u64)).encode(body)?;
+            compressor.stats.record_throughput(body.len() as u64, start.elapsed());
+            compressor.finish(&req.headers, &Decision::Compress(encoding), coding, Some(body.len() as u64), Some(&compressed), res.headers_mut());
+
+            if coding == Coding::Transfer {
+                // hyper only adds the chunked transfer-coding to bodies of unknown length
+                let mut res = res.start()?;
+                res.write_all(&compressed)?;
+                res.end()
+            } else {
+                res.send(&compressed)
+            }
+        }
+        Plan::Skip { reason, coding } => {
+            compressor.finish(&req.headers, &Decision::Skip(reason), coding, None, None, res.headers_mut());
+            if let Coding::Transcoded(from) = coding {
+                res.send(&decode(from, body)?)
+            } else {
+                res.send(body)
+            }
+        }
+    }
+}
+
+/// Starts a streaming response, compressed if the client accepts it. The `Content-Length`
+/// header is used to decide whether compression pays off, and how to compress, and is removed
+/// for compressed responses. `ResponseWriter::end` has to be called once the whole body is
+/// written.
+///
+/// Streamed responses never get digests, since their headers are sent before the body, and a
+/// `Content-Digest` set by the handler is removed once the body is encoded or decoded.
+/// Transcoded responses are read into memory to decode them.
+pub fn start<'a>(req: &Request, mut res: Response<'a, Fresh>, compressor: &Compressor) -> io::Result<ResponseWriter<'a>> {
+    let plan = compressor.plan(&facts(req, res.headers(), None));
+    res.headers_mut().remove::<Sensitive>();
+
+    match plan {
+        Plan::Compress { encoding, coding, level, permit } => {
+            let content_length = match coding {
+                Coding::Transcoded(_) => None,
+                _ => res.headers().get::<ContentLength>().map(|&ContentLength(length)| length),
+            };
+            compressor.finish(&req.headers, &Decision::Compress(encoding), coding, content_length, None, res.headers_mut());
+
+            let body_encoding = compressor.body_encoding(encoding, level, content_length);
+            let encoded = Writer::Encoded(Box::new(body_encoding.encoder(res.start()?)?));
+            let writer = match coding {
+                Coding::Transcoded(from) => Writer::Transcoded(from, Vec::new(), Box::new(encoded)),
+                _ => encoded,
+            };
+            Ok(ResponseWriter { writer, _permit: permit, _in_flight: Some(compressor.stats.start_compression()) })
+        }
+        Plan::Skip { reason, coding } => {
+            compressor.finish(&req.headers, &Decision::Skip(reason), coding, None, None, res.headers_mut());
+            let writer = if let Coding::Transcoded(from) = coding {
+                Writer::Transcoded(from, Vec::new(), Box::new(Writer::Identity(res.start()?)))
+            } else {
+                Writer::Identity(res.start()?)
+            };
+            Ok(ResponseWriter { writer, _permit: None, _in_flight: None })
+        }
+    }
+}
+
+/// How a streaming response is written
+enum Writer<'a> {
+    /// As is
+    Identity(Response<'a, Streaming>),
+    /// Compressed while it is written
+    Encoded(Box<BodyEncoder<Response<'a, Streaming>>>),
+    /// Read into memory, and decoded into the inner writer once it ends
+    Transcoded(CompressionEncoding, Vec<u8>, Box<Writer<'a>>),
+}
+
+impl<'a> Writer<'a> {
+    fn end(self) -> io::Result<()> {
+        match self {
+            Writer::Identity(res) => res.end(),
+            Writer::Encoded(encoder) => encoder.finish()?.end(),
+            Writer::Transcoded(from, encoded, mut inner) => {
+                inner.write_all(&decode(from, &encoded)?)?;
+                inner.end()
+            }
+        }
+    }
+}
+
+impl<'a> Write for Writer<'a> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match *self {
+            Writer::Identity(ref mut res) => res.write(buf),
+            Writer::Encoded(ref mut encoder) => encoder.write(buf),
+            Writer::Transcoded(_, ref mut encoded, _) => encoded.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match *self {
+            Writer::Identity(ref mut res) => res.flush(),
+            Writer::Encoded(ref mut encoder) => encoder.flush(),
+            Writer::Transcoded(..) => Ok(()),
+        }
+    }
+}
+
+/// A streaming hyper response that is possibly compressed. It holds its slot of the
+/// concurrency limit until it ends.
+pub struct ResponseWriter<'a> {
+    writer: Writer<'a>,
+    _permit: Option<Permit>,
+    _in_flight: Option<InFlight>,
+}
+
+impl<'a> ResponseWriter<'a> {
+    /// Completes the compressed stream, if any, and ends the response
+    pub fn end(self) -> io::Result<()> {
+        self.writer.end()
+    }
+}
+
+impl<'a> Write for ResponseWriter<'a> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.writer.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
@@ -0,0 +1,153 @@
+//! Picks the compression level of each response from its size and the load of the server.
+
+use codec::{default_level, max_level};
+use config::Config;
+use negotiation::CompressionEncoding;
+
+const DEFAULT_LARGE_BODY: u64 = 1024 * 1024;
+const DEFAULT_BUSY_CONCURRENCY: usize = 4;
+const DEFAULT_TARGET_THROUGHPUT: u64 = 20 * 1024 * 1024;
+const FASTEST_LEVEL: u32 = 1;
+
+/// The level to compress a response with when the load of the server is not taken into account
+pub fn fixed_level(config: &Config, encoding: CompressionEncoding, content_length: Option<u64>) -> u32 {
+    if config.is_oversize(content_length) {
+        FASTEST_LEVEL
+    } else {
+        default_level(encoding)
+    }
+}
+
+/// **Adaptive Compression Level**
+///
+/// Lowers the compression level of a response below the maximum of its encoding the larger it
+/// is, the more responses are compressed at the same time and the slower the encoders have
+/// been recently. Each of these three factors alone can lower the level halfway to the
+/// minimum, so a large response on a busy server is compressed using the fastest level.
+///
+/// # Example
+/// ```rust
+/// use iron_pack::{AdaptiveLevel, Config, CompressionEncoding};
+///
+/// # #[cfg(feature = "brotli")]
+/// # fn main() {
+/// let config = Config::default().adaptive_level(AdaptiveLevel::default()
+///     .bounds(CompressionEncoding::Brotli, 4, 9)
+///     .busy_concurrency(8));
+/// # }
+/// # #[cfg(not(feature = "brotli"))]
+/// # fn main() {}
+/// ```
+#[derive(Clone, Debug)]
+pub struct AdaptiveLevel {
+    bounds: Vec<(CompressionEncoding, u32, u32)>,
+    large_body: u64,
+    busy_concurrency: usize,
+    target_throughput: u64,
+}
+
+impl Default for AdaptiveLevel {
+    fn default() -> AdaptiveLevel {
+        AdaptiveLevel {
+            bounds: Vec::new(),
+            large_body: DEFAULT_LARGE_BODY,
+            busy_concurrency: DEFAULT_BUSY_CONCURRENCY,
+            target_throughput: DEFAULT_TARGET_THROUGHPUT,
+        }
+    }
+}
+
+impl AdaptiveLevel {
+    /// The lowest and highest level used for an encoding (default: from 1 up to the level the
+    /// encoding uses without this policy)
+    pub fn bounds(mut self, encoding: CompressionEncoding, min: u32, max: u32) -> AdaptiveLevel {
+        let max = max.min(max_level(encoding));
+        self.bounds.retain(|&(bounded, _, _)| bounded != encoding);
+        self.bounds.push((encoding, min.min(max), max));
+        self
+    }
+
+    /// Responses with a `Content-Length` of at least this many bytes count as large
+    /// (default: 1 MiB)
+    pub fn large_body(mut self, bytes: u64) -> AdaptiveLevel {
+        self.large_body = bytes.max(1);
+        self
+    }
+
+    /// The number of concurrent compressions at which the server counts as busy (default: 4)
+    pub fn busy_concurrency(mut self, compressions: usize) -> AdaptiveLevel {
+        self.busy_concurrency = compressions.max(1);
+        self
+    }
+
+    /// The encoder throughput in bytes per second below which the encoders count as slow
+    /// (default: 20 MiB/s)
+    pub fn target_throughput(mut self, bytes_per_second: u64) -> AdaptiveLevel {
+        self.target_throughput = bytes_per_second.max(1);
+        self
+    }
+
+    fn bounds_of(&self, encoding: CompressionEncoding) -> (u32, u32) {
+        self.bounds
+            .iter()
+            .find(|&&(bounded, _, _)| bounded == encoding)
+            .map(|&(_, min, max)| (min, max))
+            .unwrap_or_else(|| {
+                let max = default_level(encoding);
+                (max.min(1), max)
+            })
+    }
+
+    /// The level to compress a response with, given the number of compressions already in
+    /// progress and the recent encoder throughput, if known
+    pub(crate) fn level(&self, encoding: CompressionEncoding, content_length: Option<u64>, in_flight: usize, throughput: Option<u64>) -> u32 {
+        let (min, max) = self.bounds_of(encoding);
+
+        let size = content_length.map_or(0.0, |length| ratio(length as f64, self.large_body as f64));
+        let load = ratio(in_flight as f64, self.busy_concurrency as f64);
+        let slowness = throughput.map_or(0.0, |throughput| 1.0 - ratio(throughput as f64, self.target_throughput as f64));
+        let pressure = ((size + load + slowness) / 2.0).min(1.0);
+
+        max - (f64::from(max - min) * pressure).round() as u32
+    }
+}
+
+fn ratio(value: f64, full: f64) -> f64 {
+    (value / full).min(1.0)
+}
+
+#[cfg(all(test, feature = "brotli"))]
+mod tests {
+    use super::AdaptiveLevel;
+    use negotiation::CompressionEncoding::Brotli;
+
+    #[test]
+    fn idle_server_and_small_body_use_the_maximum() {
+        assert_eq!(AdaptiveLevel::default().level(Brotli, Some(1000), 0, None), 8);
+    }
+
+    #[test]
+    fn large_body_on_idle_server_uses_a_medium_level() {
+        let policy = AdaptiveLevel::default().bounds(Brotli, 2, 10);
+        assert_eq!(policy.level(Brotli, Some(10 * 1024 * 1024), 0, None), 6);
+    }
+
+    #[test]
+    fn large_body_on_busy_server_uses_the_minimum() {
+        let policy = AdaptiveLevel::default().bounds(Brotli, 2, 10).busy_concurrency(2);
+        assert_eq!(policy.level(Brotli, Some(10 * 1024 * 1024), 2, None), 2);
+    }
+
+    #[test]
+    fn slow_encoders_lower_the_level() {
+        let policy = AdaptiveLevel::default().bounds(Brotli, 2, 10).target_throughput(1000);
+        assert_eq!(policy.level(Brotli, Some(0), 0, Some(2000)), 10);
+        assert_eq!(policy.level(Brotli, Some(0), 0, Some(0)), 6);
+    }
+
+    #[test]
+    fn bounds_are_clamped_to_the_encoding() {
+        let policy = AdaptiveLevel::default().bounds(Brotli, 20, 30);
+        assert_eq!(policy.level(Brotli, Some(10 * 1024 * 1024), 100, Some(0)), 11);
+    }
+}
@@ -3,175 +3,116 @@
 //! by providing an AfterMiddleware for your iron server.
 
 extern crate iron;
+extern crate hyper;
+extern crate mime_guess;
+#[cfg(any(feature = "libflate", test))]
 extern crate libflate;
+#[cfg(feature = "flate2")]
+extern crate flate2;
+#[cfg(any(feature = "brotli", test))]
 extern crate brotli;
-
-use std::io;
-use std::io::Write;
-use iron::prelude::*;
-use iron::headers::*;
-use iron::{AfterMiddleware};
-
-use iron::headers::Encoding;
-use iron::response::WriteBody;
-
-const DEFAULT_MIN_BYTES_FOR_COMPRESSION: u64 = 860;
-
-#[derive(PartialEq, Clone, Debug)]
-enum CompressionEncoding {
-    Brotli,
-    Deflate,
-    Gzip,
-}
-
-struct BrotliBody(Box<WriteBody>);
-
-impl WriteBody for BrotliBody {
-    fn write_body(&mut self, w: &mut Write) -> io::Result<()> {
-        const BUFFER_SIZE: usize = 4096;
-        const QUALITY: u32 = 8;
-        const LG_WINDOW_SIZE: u32 = 20;
-        let mut encoder = brotli::CompressorWriter::new(w, BUFFER_SIZE, QUALITY, LG_WINDOW_SIZE);
-        self.0.write_body(&mut encoder)?;
-        Ok(())
-    }
-}
-
-struct GzipBody(Box<WriteBody>);
-
-impl WriteBody for GzipBody {
-    fn write_body(&mut self, w: &mut Write) -> io::Result<()> {
-        let mut encoder = libflate::gzip::Encoder::new(w)?;
-        self.0.write_body(&mut encoder)?;
-        encoder.finish().into_result().map(|_| ())
-    }
-}
-
-struct DeflateBody(Box<WriteBody>);
-
-impl WriteBody for DeflateBody {
-    fn write_body(&mut self, w: &mut Write) -> io::Result<()> {
-        let mut encoder = libflate::deflate::Encoder::new(w);
-        self.0.write_body(&mut encoder)?;
-        encoder.finish().into_result().map(|_| ())
-    }
-}
-
-fn encoding_matches_header(encoding: &CompressionEncoding, header: &Encoding) -> bool {
-    match encoding {
-        &CompressionEncoding::Brotli => *header == Encoding::EncodingExt(String::from("br")),
-        &CompressionEncoding::Deflate => *header == Encoding::Deflate,
-        &CompressionEncoding::Gzip => *header == Encoding::Gzip || *header == Encoding::EncodingExt(String::from("*")),
-    }
-}
-
-fn get_body(encoding: &CompressionEncoding, wrapped_body: Box<WriteBody>) -> Box<WriteBody> {
-    match encoding {
-        &CompressionEncoding::Brotli => Box::new(BrotliBody(wrapped_body)),
-        &CompressionEncoding::Deflate => Box::new(DeflateBody(wrapped_body)),
-        &CompressionEncoding::Gzip => Box::new(GzipBody(wrapped_body)),
-    }
-}
-
-fn get_header(encoding: &CompressionEncoding) -> Encoding {
-    match encoding {
-        &CompressionEncoding::Brotli => Encoding::EncodingExt(String::from("br")),
-        &CompressionEncoding::Deflate => Encoding::Deflate,
-        &CompressionEncoding::Gzip => Encoding::Gzip,
-    }
-}
-
-fn which_compression<'a, 'b>(req: &'b Request, res: &'b Response, priority: &Vec<CompressionEncoding>) -> Option<CompressionEncoding> {
-    return match (res.headers.get::<iron::headers::ContentEncoding>(), res.headers.get::<ContentLength>(), req.headers.get::<AcceptEncoding>()) {
-        (None, Some(content_length), Some(&AcceptEncoding(ref quality_items))) => {
-            if (content_length as &u64) < &DEFAULT_MIN_BYTES_FOR_COMPRESSION {
-                return None;
-            }
-
-            let max_quality = quality_items.iter().map(|qi| qi.quality).max();
-
-            if let Some(max_quality) = max_quality {
-                let quality_items: Vec<&QualityItem<Encoding>> = quality_items
-                    .iter()
-                    .filter(|qi| qi.quality != Quality(0) && qi.quality == max_quality)
-                    .collect();
-
-                return priority
-                    .iter()
-                    .filter(|ce| quality_items.iter().find(|qi| {
-                        encoding_matches_header(ce, &qi.item)
-                    }).is_some())
-                    .nth(0)
-                    .map(|ce| ce.clone());
-            }
-            None
-        }
-        _ => None
-    };
-}
-
-/// **Compression Middleware**
-///
-/// Currently either compresses using brotli, gzip or deflate algorithms. The algorithm is
-/// chosen by evaluating the `AcceptEncoding` header sent by the client.
-///
-/// # Example
-/// ```rust,no_run
-/// extern crate iron;
-/// extern crate iron_pack;
-///
-/// use iron::prelude::*;
-/// use iron_pack::CompressionMiddleware;
-///
-/// fn a_lot_of_batman(_: &mut Request) -> IronResult<Response> {
-///     let nana = "Na".repeat(5000);
-///     Ok(Response::with((iron::status::Ok, format!("{}, Batman!", nana))))
-/// }
-///
-/// fn main() {
-///     let mut chain = Chain::new(a_lot_of_batman);
-///     chain.link_after(CompressionMiddleware);
-///     Iron::new(chain).http("localhost:3000").unwrap();
-/// }
-/// ```
-pub struct CompressionMiddleware;
-
-impl AfterMiddleware for CompressionMiddleware {
-
-    /// Implementation of the compression middleware
-    fn after(&self, req: &mut Request, mut res: Response) -> IronResult<Response> {
-        let brotli = CompressionEncoding::Brotli;
-        let deflate = CompressionEncoding::Deflate;
-        let gzip = CompressionEncoding::Gzip;
-        let default_priorities = vec!(brotli, gzip, deflate);
-
-        if res.body.is_some() {
-            if let Some(compression) = which_compression(&req, &res, &default_priorities) {
-                res.headers.set(ContentEncoding(vec![get_header(&compression)]));
-                res.headers.remove::<ContentLength>();
-                res.body = Some(get_body(&compression, res.body.take().unwrap()));
-            }
-        }
-
-        Ok(res)
-    }
-}
+#[cfg(feature = "libdeflate")]
+extern crate libdeflater;
+#[cfg(feature = "digest")]
+extern crate sha2;
+#[cfg(feature = "digest")]
+extern crate base64;
+#[cfg(feature = "zstd")]
+extern crate zstd;
+#[cfg(feature = "padding")]
+extern crate rand;
+#[cfg(feature = "embed")]
+extern crate iron_pack_macros;
+// `include_compressed!` refers to `::iron_pack`, which the tests expand it within
+#[cfg(all(test, feature = "embed"))]
+extern crate self as iron_pack;
+#[cfg(feature = "testing")]
+extern crate iron_test;
+
+#[cfg(not(any(feature = "gzip", feature = "deflate", feature = "brotli", feature = "zstd")))]
+compile_error!("iron-pack needs at least one of the `gzip`, `deflate`, `brotli` or `zstd` features");
+
+#[cfg(all(any(feature = "gzip", feature = "deflate"), not(any(feature = "libflate", feature = "flate2-rust", feature = "flate2-zlib"))))]
+compile_error!("the `gzip` and `deflate` features need one of the `libflate`, `flate2-rust` or `flate2-zlib` backends");
+
+mod accept_encoding;
+#[cfg(feature = "analysis")]
+pub mod analysis;
+mod breach;
+pub mod codec;
+mod compressor;
+mod config;
+#[cfg(feature = "precompress")]
+mod content_type;
+mod debug;
+#[cfg(feature = "digest")]
+mod digest;
+#[cfg(feature = "embed")]
+mod embed;
+mod encoding;
+mod level;
+mod limit;
+#[cfg(feature = "hyper")]
+pub mod hyper_adapter;
+mod middleware;
+mod negotiation;
+#[cfg(feature = "parallel")]
+mod parallel;
+#[cfg(feature = "parallel")]
+mod pool;
+#[cfg(feature = "precompress")]
+pub mod precompress;
+mod rules;
+mod static_files;
+mod stats;
+#[cfg(feature = "testing")]
+pub mod testing;
+
+pub use breach::{BreachMitigation, Sensitive};
+pub use codec::encode;
+pub use compressor::Compressor;
+pub use config::{Config, OversizePolicy};
+#[cfg(feature = "digest")]
+pub use digest::DigestAlgorithm;
+#[cfg(feature = "embed")]
+pub use embed::{EmbeddedAsset, EmbeddedAssets};
+#[cfg(feature = "embed")]
+pub use iron_pack_macros::include_compressed;
+pub use level::AdaptiveLevel;
+pub use limit::SaturationPolicy;
+pub use middleware::{negotiate, negotiate_transfer, CompressionMiddleware};
+pub use negotiation::{negotiate_exchange, negotiate_transfer_coding, CompressionEncoding, Decision, Exchange, SkipReason, Strategy};
+#[cfg(feature = "parallel")]
+pub use parallel::ParallelGzip;
+pub use rules::{RouteId, Rule, RuleLevel};
+pub use static_files::StaticFiles;
+pub use stats::Stats;
 
 #[cfg(test)]
+#[allow(dead_code)]
 mod test_common {
     extern crate iron_test;
 
+    use std::env;
+    use std::fs;
     use std::io::Read;
+    use std::path::PathBuf;
     use iron::prelude::*;
     use iron::headers::*;
-    use iron::{Chain, status};
+    use iron::{Chain, Handler, status};
     use iron::modifiers::Header;
+    use iron::typemap::Key;
     use self::iron_test::{request};
+    use libflate::gzip;
 
-    use super::CompressionMiddleware;
+    use super::{CompressionMiddleware, Config, Decision, negotiate};
 
-    pub fn build_compressed_echo_chain(with_encoding: bool) -> Chain {
-        let mut chain = Chain::new(move |req: &mut Request| {
+    struct NegotiatedDecision;
+
+    impl Key for NegotiatedDecision { type Value = Decision; }
+
+    fn build_echo_chain(with_encoding: bool) -> Chain {
+        Chain::new(move |req: &mut Request| {
             let mut body: Vec<u8> = vec!();
             req.body.read_to_end(&mut body).unwrap();
 
@@ -180,11 +121,41 @@ mod test_common {
             } else {
                 Ok(Response::with((status::Ok, Header(ContentEncoding(vec![Encoding::Chunked])), body)))
             }
+        })
+    }
+
+    pub fn build_compressed_echo_chain(with_encoding: bool) -> Chain {
+        let mut chain = build_echo_chain(with_encoding);
+        chain.link_after(CompressionMiddleware::new());
+        return chain;
+    }
+
+    pub fn build_configured_echo_chain(config: Config) -> Chain {
+        let mut chain = build_echo_chain(false);
+        chain.link_after(CompressionMiddleware::with_config(config));
+        return chain;
+    }
+
+    pub fn build_middleware_echo_chain(middleware: CompressionMiddleware) -> Chain {
+        let mut chain = build_echo_chain(false);
+        chain.link_after(middleware);
+        return chain;
+    }
+
+    pub fn build_negotiating_echo_chain(with_encoding: bool, config: Config) -> Chain {
+        let mut chain = build_echo_chain(with_encoding);
+        chain.link_after(move |req: &mut Request, mut res: Response| {
+            let decision = negotiate(req, &res, &config);
+            res.extensions.insert::<NegotiatedDecision>(decision);
+            Ok(res)
         });
-        chain.link_after(CompressionMiddleware);
         return chain;
     }
 
+    pub fn negotiated_decision(res: &Response) -> Decision {
+        *res.extensions.get::<NegotiatedDecision>().unwrap()
+    }
+
     pub fn post_data_with_accept_encoding(data: &str, accept_encoding: Option<AcceptEncoding>, chain: &Chain) -> Response {
         let mut headers = Headers::new();
         if let Some(value) = accept_encoding {
@@ -196,6 +167,61 @@ mod test_common {
                              data,
                              chain).unwrap();
     }
+
+    pub fn post_data_with_raw_accept_encoding(data: &str, accept_encoding: &str, chain: &Chain) -> Response {
+        let mut headers = Headers::new();
+        headers.set_raw("Accept-Encoding", vec![accept_encoding.as_bytes().to_vec()]);
+
+        return request::post("http://localhost:3000/",
+                             headers,
+                             data,
+                             chain).unwrap();
+    }
+
+    pub fn post_data_with_raw_headers(path: &str, data: &str, raw: &[(&str, &str)], chain: &Chain) -> Response {
+        let mut headers = Headers::new();
+        for &(name, value) in raw {
+            headers.set_raw(name.to_owned(), vec![value.as_bytes().to_vec()]);
+        }
+
+        return request::post(&format!("http://localhost:3000{}", path),
+                             headers,
+                             data,
+                             chain).unwrap();
+    }
+
+    pub fn get_with_raw_accept_encoding<H: Handler>(path: &str, accept_encoding: &str, handler: &H) -> Response {
+        let mut headers = Headers::new();
+        headers.set_raw("Accept-Encoding", vec![accept_encoding.as_bytes().to_vec()]);
+
+        return request::get(&format!("http://localhost:3000{}", path),
+                            headers,
+                            handler).unwrap();
+    }
+
+    pub fn content_encoding(res: &Response) -> Option<String> {
+        res.headers.get::<ContentEncoding>().map(|content_encoding| content_encoding.0[0].to_string())
+    }
+
+    pub fn gunzip(compressed_bytes: &[u8]) -> Vec<u8> {
+        let mut decoder = gzip::Decoder::new(compressed_bytes).unwrap();
+        let mut decoded_data = Vec::new();
+        decoder.read_to_end(&mut decoded_data).unwrap();
+        decoded_data
+    }
+
+    /// A fresh directory below the temporary directory holding `files`, which may be nested
+    pub fn temp_dir(name: &str, files: &[(&str, &[u8])]) -> PathBuf {
+        let dir = env::temp_dir().join(format!("iron-pack-{}-{}", name, ::std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        for &(file, content) in files {
+            let path = dir.join(file);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, content).unwrap();
+        }
+        dir
+    }
 }
 
 #[cfg(test)]
@@ -254,15 +280,18 @@ mod uncompressable_tests {
     }
 }
 
-#[cfg(test)]
+#[cfg(all(feature = "gzip", test))]
 mod gzip_tests {
     extern crate iron_test;
 
     use std::io::Read;
+    use iron::prelude::*;
     use iron::headers::*;
+    use iron::status;
     use self::iron_test::{response};
     use libflate::gzip;
 
+    use super::CompressionMiddleware;
     use super::test_common::*;
 
     #[test]
@@ -282,9 +311,27 @@ mod gzip_tests {
         decoder.read_to_end(&mut decoded_data).unwrap();
         assert_eq!(decoded_data, value.into_bytes());
     }
+
+    #[test]
+    fn it_should_remove_the_content_digest_of_the_handler_from_compressed_responses() {
+        let mut chain = Chain::new(|_: &mut Request| {
+            let mut res = Response::with((status::Ok, "a".repeat(1000)));
+            res.headers.set_raw("Content-Digest", vec![b"sha-256=:aGFuZGxlcg==:".to_vec()]);
+            Ok(res)
+        });
+        chain.link_after(CompressionMiddleware::new());
+
+        let res = post_data_with_raw_accept_encoding("", "gzip", &chain);
+        assert_eq!(res.headers.get::<ContentEncoding>(), Some(&ContentEncoding(vec![Encoding::Gzip])));
+        assert_eq!(res.headers.get_raw("Content-Digest"), None);
+
+        let res = post_data_with_raw_accept_encoding("", "identity", &chain);
+        assert_eq!(res.headers.get::<ContentEncoding>(), None);
+        assert!(res.headers.get_raw("Content-Digest").is_some());
+    }
 }
 
-#[cfg(test)]
+#[cfg(all(feature = "deflate", test))]
 mod deflate_tests {
     extern crate iron_test;
 
@@ -314,7 +361,7 @@ mod deflate_tests {
     }
 }
 
-#[cfg(test)]
+#[cfg(all(feature = "brotli", test))]
 mod brotli_tests {
     extern crate iron_test;
 
@@ -346,7 +393,32 @@ mod brotli_tests {
     }
 }
 
-#[cfg(test)]
+#[cfg(all(feature = "zstd", test))]
+mod zstd_tests {
+    extern crate iron_test;
+
+    use iron::headers::*;
+    use self::iron_test::{response};
+
+    use super::CompressionEncoding;
+    use super::codec::decode;
+    use super::test_common::*;
+
+    #[test]
+    fn it_should_compress_response_body_correctly_using_zstd_and_set_header() {
+        let value = "a".repeat(1000);
+        let chain = build_compressed_echo_chain(false);
+        let res = post_data_with_raw_accept_encoding(&value, "zstd", &chain);
+
+        assert_eq!(res.headers.get::<ContentLength>(), None);
+        assert_eq!(res.headers.get::<ContentEncoding>(), Some(&ContentEncoding(vec![Encoding::EncodingExt(String::from("zstd"))])));
+
+        let compressed_bytes = response::extract_body_to_bytes(res);
+        assert_eq!(decode(CompressionEncoding::Zstd, &compressed_bytes).unwrap(), value.into_bytes());
+    }
+}
+
+#[cfg(all(feature = "gzip", feature = "deflate", feature = "brotli", test))]
 mod priority_tests {
     use iron::headers::*;
 
@@ -438,72 +510,2360 @@ mod priority_tests {
     }
 }
 
-#[cfg(all(feature = "unstable", test))]
-mod middleware_benchmarks {
-    macro_rules! bench_chain_with_header_and_size {
-        ($name:ident, $chain:expr, $header:expr, $response_size:expr) => {
-            #[bench]
-            fn $name(b: &mut Bencher) {
-                let chain = $chain;
-                let mut rng = rand::IsaacRng::new_unseeded();
+#[cfg(test)]
+mod accept_encoding_tests {
+    use iron::headers::*;
 
-                b.iter(|| {
-                    let data: String = rng.gen_ascii_chars().take($response_size).collect();
-                    let res = post_data_with_accept_encoding(&data,
-                                                           $header,
-                                                           &chain);
-                    let compressed_bytes = response::extract_body_to_bytes(res);
+    use super::accept_encoding::{parse_lenient, MalformedAcceptEncoding};
 
-                    assert!(compressed_bytes.len() > 0);
-                })
-            }
-        };
+    fn parse(value: &str) -> Result<Vec<QualityItem<Encoding>>, MalformedAcceptEncoding> {
+        parse_lenient(&[value.as_bytes().to_vec()])
     }
 
-    macro_rules! bench_chains_with_size {
-        ($mod_name:ident, $size:expr) => {
-            mod $mod_name {
-                extern crate iron_test;
-                extern crate test;
-                extern crate rand;
+    #[test]
+    fn it_should_parse_a_well_formed_header() {
+        assert_eq!(parse("gzip, deflate;q=0.5"), Ok(vec![
+            qitem(Encoding::Gzip),
+            QualityItem::new(Encoding::Deflate, q(0.5)),
+        ]));
+    }
 
-                use std::io::Read;
-                use iron::prelude::*;
-                use iron::{Chain, status};
-                use iron::headers::*;
-                use self::test::Bencher;
-                use self::iron_test::{response};
-                use self::rand::Rng;
-                use super::super::test_common::*;
+    #[test]
+    fn it_should_tolerate_whitespace_and_trailing_commas() {
+        assert_eq!(parse(" gzip ; q = 0.8 ,, br ,"), Ok(vec![
+            QualityItem::new(Encoding::Gzip, q(0.8)),
+            qitem(Encoding::EncodingExt(String::from("br"))),
+        ]));
+    }
 
-                fn build_echo_chain() -> Chain {
-                    let chain = Chain::new(|req: &mut Request| {
-                        let mut body: Vec<u8> = vec!();
-                        req.body.read_to_end(&mut body).unwrap();
-                        Ok(Response::with((status::Ok, body)))
-                    });
-                    return chain;
-                }
+    #[test]
+    fn it_should_lowercase_codings() {
+        assert_eq!(parse("GZIP, Br"), Ok(vec![
+            qitem(Encoding::Gzip),
+            qitem(Encoding::EncodingExt(String::from("br"))),
+        ]));
+    }
 
-                bench_chain_with_header_and_size!(without_middleware, build_echo_chain(), None, $size);
-                bench_chain_with_header_and_size!(with_middleware_no_accept_header, build_compressed_echo_chain(false), None, $size);
-                bench_chain_with_header_and_size!(with_middleware_gzip,
-                                                  build_compressed_echo_chain(false),
-                                                  Some(AcceptEncoding(vec![qitem(Encoding::Gzip)])),
-                                                  $size);
-                bench_chain_with_header_and_size!(with_middleware_deflate,
-                                                  build_compressed_echo_chain(false),
-                                                  Some(AcceptEncoding(vec![qitem(Encoding::Deflate)])),
-                                                  $size);
-                bench_chain_with_header_and_size!(with_middleware_brotli,
-                                                  build_compressed_echo_chain(false),
-                                                  Some(AcceptEncoding(vec![qitem(Encoding::EncodingExt(String::from("br")))])),
-                                                  $size);
-            }
-        };
+    #[test]
+    fn it_should_clamp_out_of_range_qualities() {
+        assert_eq!(parse("gzip;q=1.5, deflate;q=-1"), Ok(vec![
+            QualityItem::new(Encoding::Gzip, q(1.0)),
+            QualityItem::new(Encoding::Deflate, q(0.0)),
+        ]));
     }
 
-    bench_chains_with_size!(response_1kb, 1024);
-    bench_chains_with_size!(response_128kb, 128 * 1024);
-    bench_chains_with_size!(response_1mb, 1024 * 1024);
-}
\ No newline at end of file
+    #[test]
+    fn it_should_skip_elements_with_unparseable_qualities_or_codings() {
+        assert_eq!(parse("gzip;q=abc, de flate, br;q=0.1"), Ok(vec![
+            QualityItem::new(Encoding::EncodingExt(String::from("br")), q(0.1)),
+        ]));
+    }
+
+    #[test]
+    fn it_should_combine_multiple_header_lines() {
+        let raw = vec![b"gzip".to_vec(), b"br;q=0.5".to_vec()];
+        assert_eq!(parse_lenient(&raw), Ok(vec![
+            qitem(Encoding::Gzip),
+            QualityItem::new(Encoding::EncodingExt(String::from("br")), q(0.5)),
+        ]));
+    }
+
+    #[test]
+    fn it_should_report_headers_without_any_valid_coding() {
+        assert_eq!(parse(" , ;q=1, gzip;q=x"), Err(MalformedAcceptEncoding(String::from(" , ;q=1, gzip;q=x"))));
+    }
+}
+
+#[cfg(all(feature = "gzip", test))]
+mod lenient_accept_encoding_tests {
+    extern crate iron_test;
+
+    use iron::headers::*;
+    use self::iron_test::{response};
+
+    use super::test_common::*;
+
+    #[test]
+    fn it_should_compress_when_accept_encoding_header_is_malformed() {
+        let value = "a".repeat(1000);
+        let chain = build_compressed_echo_chain(false);
+        let res = post_data_with_raw_accept_encoding(&value, "deflate;q=0.5, gzip ; q = 1.0,", &chain);
+
+        assert_eq!(res.headers.get::<ContentEncoding>(), Some(&ContentEncoding(vec![Encoding::Gzip])));
+    }
+
+    #[test]
+    fn it_should_not_compress_when_nothing_can_be_recovered_from_accept_encoding_header() {
+        let value = "a".repeat(1000);
+        let chain = build_compressed_echo_chain(false);
+        let res = post_data_with_raw_accept_encoding(&value, "gzip;q=high, ;", &chain);
+
+        assert_eq!(res.headers.get::<ContentEncoding>(), None);
+        assert_eq!(response::extract_body_to_string(res), value);
+    }
+}
+
+#[cfg(all(feature = "gzip", feature = "deflate", feature = "brotli", test))]
+mod negotiation_tests {
+    use super::{negotiate_exchange, negotiate_transfer_coding, Config, CompressionEncoding, Decision, Exchange, OversizePolicy, SkipReason, Strategy};
+    use super::test_common::*;
+
+    fn negotiate_with(data: &str, accept_encoding: &str, config: Config) -> Decision {
+        let chain = build_negotiating_echo_chain(false, config);
+        negotiated_decision(&post_data_with_raw_accept_encoding(data, accept_encoding, &chain))
+    }
+
+    #[test]
+    fn it_should_choose_the_encoding_the_middleware_would_use() {
+        let value = "a".repeat(1000);
+        assert_eq!(negotiate_with(&value, "gzip, deflate", Config::default()),
+                   Decision::Compress(CompressionEncoding::Gzip));
+    }
+
+    #[test]
+    fn it_should_respect_the_configured_priority() {
+        let value = "a".repeat(1000);
+        let config = Config::default().priority(vec![CompressionEncoding::Deflate, CompressionEncoding::Brotli]);
+        assert_eq!(negotiate_with(&value, "br, deflate", config),
+                   Decision::Compress(CompressionEncoding::Deflate));
+    }
+
+    #[test]
+    fn it_should_follow_the_client_order_with_the_client_order_strategy() {
+        let value = "a".repeat(1000);
+        let config = Config::default().strategy(Strategy::ClientOrder);
+        assert_eq!(negotiate_with(&value, "deflate, gzip, br", config.clone()),
+                   Decision::Compress(CompressionEncoding::Deflate));
+        assert_eq!(negotiate_with(&value, "deflate;q=0.5, gzip, br", config.clone()),
+                   Decision::Compress(CompressionEncoding::Deflate));
+        assert_eq!(negotiate_with(&value, "deflate;q=0, gzip;q=0.1, br", config),
+                   Decision::Compress(CompressionEncoding::Gzip));
+    }
+
+    #[test]
+    fn it_should_follow_the_server_order_with_the_server_order_strategy() {
+        let value = "a".repeat(1000);
+        let config = Config::default().strategy(Strategy::ServerOrder);
+        assert_eq!(negotiate_with(&value, "gzip, br;q=0.1", config.clone()),
+                   Decision::Compress(CompressionEncoding::Brotli));
+        assert_eq!(negotiate_with(&value, "gzip, br;q=0", config),
+                   Decision::Compress(CompressionEncoding::Gzip));
+    }
+
+    #[test]
+    fn it_should_weigh_client_qualities_with_the_weighted_strategy() {
+        let value = "a".repeat(1000);
+        let config = Config::default().strategy(Strategy::Weighted(vec![(CompressionEncoding::Brotli, 0.5)]));
+        assert_eq!(negotiate_with(&value, "br, gzip", config.clone()),
+                   Decision::Compress(CompressionEncoding::Gzip));
+        assert_eq!(negotiate_with(&value, "br, gzip;q=0.4", config.clone()),
+                   Decision::Compress(CompressionEncoding::Brotli));
+        assert_eq!(negotiate_with(&value, "br, gzip;q=0", config),
+                   Decision::Compress(CompressionEncoding::Brotli));
+    }
+
+    #[test]
+    fn it_should_report_refused_encodings_with_every_strategy() {
+        let value = "a".repeat(1000);
+        for strategy in vec![Strategy::ClientQuality, Strategy::ClientOrder, Strategy::ServerOrder, Strategy::Weighted(vec![])] {
+            let config = Config::default().strategy(strategy);
+            assert_eq!(negotiate_with(&value, "br;q=0, gzip;q=0, deflate;q=0", config.clone()),
+                       Decision::Skip(SkipReason::QualityZero));
+            assert_eq!(negotiate_with(&value, "identity", config),
+                       Decision::Skip(SkipReason::NoAcceptableEncoding));
+        }
+    }
+
+    #[test]
+    fn it_should_respect_the_configured_minimum_size() {
+        assert_eq!(negotiate_with("aaaaaaaaaa", "gzip", Config::default()), Decision::Skip(SkipReason::TooSmall));
+        assert_eq!(negotiate_with("aaaaaaaaaa", "gzip", Config::default().min_bytes(10)),
+                   Decision::Compress(CompressionEncoding::Gzip));
+    }
+
+    #[test]
+    fn it_should_respect_the_configured_maximum_size() {
+        let value = "a".repeat(1001);
+        assert_eq!(negotiate_with(&value, "gzip", Config::default().max_bytes(1000, OversizePolicy::Skip)),
+                   Decision::Skip(SkipReason::TooLarge));
+        assert_eq!(negotiate_with(&value, "gzip", Config::default().max_bytes(1001, OversizePolicy::Skip)),
+                   Decision::Compress(CompressionEncoding::Gzip));
+        assert_eq!(negotiate_with(&value, "gzip", Config::default().max_bytes(1000, OversizePolicy::Fastest)),
+                   Decision::Compress(CompressionEncoding::Gzip));
+    }
+
+    #[test]
+    fn it_should_skip_responses_of_unknown_length_whatever_the_maximum_size() {
+        for &policy in &[OversizePolicy::Skip, OversizePolicy::Fastest] {
+            let config = Config::default().max_bytes(1000, policy);
+            let accept_encoding = [b"gzip".to_vec()];
+            let exchange = Exchange { accept_encoding: Some(&accept_encoding), has_body: true, ..Exchange::default() };
+            assert_eq!(negotiate_exchange(&exchange, &config), Decision::Skip(SkipReason::UnknownLength));
+        }
+    }
+
+    #[test]
+    fn it_should_skip_already_encoded_responses() {
+        let value = "a".repeat(1000);
+        let chain = build_negotiating_echo_chain(true, Config::default());
+        let res = post_data_with_raw_accept_encoding(&value, "gzip", &chain);
+        assert_eq!(negotiated_decision(&res), Decision::Skip(SkipReason::AlreadyEncoded));
+    }
+
+    #[test]
+    fn it_should_skip_requests_without_accept_encoding() {
+        let value = "a".repeat(1000);
+        let chain = build_negotiating_echo_chain(false, Config::default());
+        let res = post_data_with_accept_encoding(&value, None, &chain);
+        assert_eq!(negotiated_decision(&res), Decision::Skip(SkipReason::NoAcceptEncoding));
+    }
+
+    #[test]
+    fn it_should_skip_requests_with_malformed_accept_encoding() {
+        let value = "a".repeat(1000);
+        assert_eq!(negotiate_with(&value, ";q=1", Config::default()), Decision::Skip(SkipReason::MalformedAcceptEncoding));
+    }
+
+    #[test]
+    fn it_should_skip_requests_refusing_all_supported_encodings() {
+        let value = "a".repeat(1000);
+        assert_eq!(negotiate_with(&value, "gzip;q=0, identity", Config::default()), Decision::Skip(SkipReason::QualityZero));
+    }
+
+    #[test]
+    fn it_should_skip_requests_preferring_unsupported_encodings() {
+        let value = "a".repeat(1000);
+        assert_eq!(negotiate_with(&value, "identity, gzip;q=0.5", Config::default()),
+                   Decision::Skip(SkipReason::NoAcceptableEncoding));
+        assert_eq!(negotiate_with(&value, "compress", Config::default()),
+                   Decision::Skip(SkipReason::NoAcceptableEncoding));
+    }
+
+    #[test]
+    fn it_should_negotiate_transfer_codings_on_the_te_header() {
+        let exchange = Exchange { has_body: true, content_length: Some(1000), ..Exchange::default() };
+        let negotiate_te = |te: &str| negotiate_transfer_coding(Some(&[te.as_bytes().to_vec()]), &exchange, &Config::default());
+        assert_eq!(negotiate_te("gzip, trailers"), Decision::Compress(CompressionEncoding::Gzip));
+        assert_eq!(negotiate_te("br, deflate;q=0.5"), Decision::Compress(CompressionEncoding::Deflate));
+        assert_eq!(negotiate_te("trailers, deflate;q=0.5, gzip;q=0.8"), Decision::Compress(CompressionEncoding::Gzip));
+        assert_eq!(negotiate_te("trailers"), Decision::Skip(SkipReason::NoTransferCoding));
+        assert_eq!(negotiate_transfer_coding(None, &exchange, &Config::default()), Decision::Skip(SkipReason::NoTransferCoding));
+
+        let small = Exchange { content_length: Some(10), ..exchange };
+        assert_eq!(negotiate_transfer_coding(Some(&[b"gzip".to_vec()]), &small, &Config::default()), Decision::Skip(SkipReason::TooSmall));
+    }
+
+    #[test]
+    fn it_should_name_skip_reasons() {
+        assert_eq!(SkipReason::TooSmall.to_string(), "too-small");
+        assert_eq!(CompressionEncoding::Brotli.to_string(), "br");
+    }
+}
+
+#[cfg(all(feature = "libdeflate", feature = "gzip", feature = "deflate", test))]
+mod one_shot_tests {
+    extern crate iron_test;
+
+    use std::fs::{self, File};
+    use std::io::Read;
+    use iron::prelude::*;
+    use iron::headers::*;
+    use iron::{Chain, status};
+    use self::iron_test::response;
+    use libflate::deflate;
+
+    use super::{CompressionEncoding, CompressionMiddleware, Config};
+    use super::codec::encode;
+    use super::test_common::*;
+
+    #[test]
+    fn it_should_compress_in_memory_gzip_response_in_one_go() {
+        let value = "a".repeat(1000);
+        let chain = build_compressed_echo_chain(false);
+        let res = post_data_with_raw_accept_encoding(&value, "gzip", &chain);
+
+        assert_eq!(res.headers.get::<ContentEncoding>(), Some(&ContentEncoding(vec![Encoding::Gzip])));
+        assert_eq!(res.headers.get::<ContentLength>(), None);
+        let compressed_bytes = response::extract_body_to_bytes(res);
+        assert_eq!(compressed_bytes, encode(CompressionEncoding::Gzip, value.as_bytes()).unwrap());
+        assert_eq!(gunzip(&compressed_bytes), value.into_bytes());
+    }
+
+    #[test]
+    fn it_should_compress_in_memory_deflate_response_in_one_go() {
+        let value = "a".repeat(1000);
+        let chain = build_compressed_echo_chain(false);
+        let res = post_data_with_raw_accept_encoding(&value, "deflate", &chain);
+
+        assert_eq!(res.headers.get::<ContentLength>(), None);
+        let compressed_bytes = response::extract_body_to_bytes(res);
+        assert_eq!(compressed_bytes, encode(CompressionEncoding::Deflate, value.as_bytes()).unwrap());
+        let mut decoder = deflate::Decoder::new(&compressed_bytes[..]);
+        let mut decoded_data = Vec::new();
+        decoder.read_to_end(&mut decoded_data).unwrap();
+        assert_eq!(decoded_data, value.into_bytes());
+    }
+
+    #[test]
+    fn it_should_stream_responses_above_the_one_shot_limit() {
+        let value = "a".repeat(1000);
+        let chain = build_configured_echo_chain(Config::default().one_shot_limit(999));
+        let res = post_data_with_raw_accept_encoding(&value, "gzip", &chain);
+
+        assert_eq!(res.headers.get::<ContentEncoding>(), Some(&ContentEncoding(vec![Encoding::Gzip])));
+        let compressed_bytes = response::extract_body_to_bytes(res);
+        assert!(compressed_bytes != encode(CompressionEncoding::Gzip, value.as_bytes()).unwrap());
+        assert_eq!(gunzip(&compressed_bytes), value.into_bytes());
+    }
+
+    #[test]
+    fn it_should_stream_file_responses() {
+        let value = "a".repeat(100_000);
+        let dir = temp_dir("one-shot", &[("body.txt", value.as_bytes())]);
+
+        let path = dir.join("body.txt");
+        let mut chain = Chain::new(move |_: &mut Request| Ok(Response::with((status::Ok, File::open(&path).unwrap()))));
+        chain.link_after(CompressionMiddleware::new());
+        let res = get_with_raw_accept_encoding("/", "gzip", &chain);
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(res.headers.get::<ContentEncoding>(), Some(&ContentEncoding(vec![Encoding::Gzip])));
+        let compressed_bytes = response::extract_body_to_bytes(res);
+        assert!(compressed_bytes != encode(CompressionEncoding::Gzip, value.as_bytes()).unwrap());
+        assert_eq!(gunzip(&compressed_bytes), value.into_bytes());
+    }
+
+    #[cfg(feature = "brotli")]
+    #[test]
+    fn it_should_stream_brotli_responses() {
+        let value = "a".repeat(1000);
+        let chain = build_compressed_echo_chain(false);
+        let res = post_data_with_raw_accept_encoding(&value, "br", &chain);
+
+        assert_eq!(res.headers.get::<ContentEncoding>(), Some(&ContentEncoding(vec![Encoding::EncodingExt(String::from("br"))])));
+        assert_eq!(res.headers.get::<ContentLength>(), None);
+        let compressed_bytes = response::extract_body_to_bytes(res);
+        assert_eq!(super::codec::decode(CompressionEncoding::Brotli, &compressed_bytes).unwrap(), value.into_bytes());
+    }
+}
+
+#[cfg(test)]
+mod limit_tests {
+    use std::sync::mpsc;
+    use std::thread;
+    use std::time::Duration;
+
+    use super::limit::ConcurrencyLimit;
+
+    #[test]
+    fn it_should_hand_out_permits_up_to_the_limit() {
+        let limit = ConcurrencyLimit::new(2);
+        let first = ConcurrencyLimit::try_acquire(&limit);
+        let second = ConcurrencyLimit::try_acquire(&limit);
+        assert!(first.is_some() && second.is_some());
+        assert!(ConcurrencyLimit::try_acquire(&limit).is_none());
+
+        drop(first);
+        assert!(ConcurrencyLimit::try_acquire(&limit).is_some());
+    }
+
+    #[test]
+    fn it_should_wait_for_a_released_permit() {
+        let limit = ConcurrencyLimit::new(1);
+        let permit = ConcurrencyLimit::try_acquire(&limit).unwrap();
+
+        let (sender, receiver) = mpsc::channel();
+        let waiting = limit.clone();
+        let handle = thread::spawn(move || {
+            let _permit = ConcurrencyLimit::acquire_timeout(&waiting, Duration::from_secs(5)).unwrap();
+            sender.send(()).unwrap();
+        });
+
+        assert!(receiver.recv_timeout(Duration::from_millis(50)).is_err());
+        drop(permit);
+        assert!(receiver.recv_timeout(Duration::from_secs(5)).is_ok());
+        handle.join().unwrap();
+    }
+
+    #[test]
+    fn it_should_give_up_waiting_after_the_timeout() {
+        let limit = ConcurrencyLimit::new(1);
+        let _permit = ConcurrencyLimit::try_acquire(&limit).unwrap();
+
+        assert!(ConcurrencyLimit::acquire_timeout(&limit, Duration::from_millis(20)).is_none());
+        assert!(ConcurrencyLimit::acquire_timeout(&limit, Duration::from_secs(0)).is_none());
+    }
+}
+
+#[cfg(all(test, feature = "brotli"))]
+mod concurrency_limit_tests {
+    extern crate iron_test;
+
+    use std::sync::{mpsc, Arc};
+    use std::thread;
+    use std::time::Duration;
+    use iron::headers::*;
+    use self::iron_test::{response};
+
+    use super::{CompressionMiddleware, Config, SaturationPolicy};
+    use super::test_common::*;
+
+    fn is_compressed(res: &iron::Response) -> bool {
+        res.headers.get::<ContentEncoding>() == Some(&ContentEncoding(vec![Encoding::EncodingExt(String::from("br"))]))
+    }
+
+    #[test]
+    fn it_should_send_responses_uncompressed_while_saturated() {
+        let value = "a".repeat(1000);
+        let middleware = CompressionMiddleware::with_config(Config::default().concurrency_limit(1, SaturationPolicy::Identity));
+        let stats = middleware.stats();
+        let chain = build_middleware_echo_chain(middleware);
+
+        // The streamed body holds its slot until it is written
+        let streaming = post_data_with_raw_accept_encoding(&value, "br", &chain);
+        assert!(is_compressed(&streaming));
+
+        let saturated = post_data_with_raw_accept_encoding(&value, "br", &chain);
+        assert!(!is_compressed(&saturated));
+        assert_eq!(response::extract_body_to_string(saturated), value);
+        assert_eq!(stats.saturated(), 1);
+        assert_eq!(stats.skipped(), 1);
+
+        response::extract_body_to_bytes(streaming);
+        let res = post_data_with_raw_accept_encoding(&value, "br", &chain);
+        assert!(is_compressed(&res));
+        assert_eq!(stats.compressed(), 2);
+    }
+
+    #[test]
+    fn it_should_queue_responses_while_saturated() {
+        let value = "a".repeat(1000);
+        let middleware = CompressionMiddleware::with_config(Config::default().concurrency_limit(1, SaturationPolicy::Queue(Duration::from_secs(5))));
+        let stats = middleware.stats();
+        let chain = Arc::new(build_middleware_echo_chain(middleware));
+
+        let streaming = post_data_with_raw_accept_encoding(&value, "br", &chain);
+
+        let (sender, receiver) = mpsc::channel();
+        let queued_chain = chain.clone();
+        let queued_value = value.clone();
+        let handle = thread::spawn(move || {
+            let res = post_data_with_raw_accept_encoding(&queued_value, "br", &queued_chain);
+            sender.send(is_compressed(&res)).unwrap();
+        });
+
+        for _ in 0..500 {
+            if stats.queued() == 1 {
+                break;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        assert_eq!(stats.queued(), 1);
+        assert!(receiver.try_recv().is_err());
+
+        drop(streaming);
+        assert_eq!(receiver.recv_timeout(Duration::from_secs(5)), Ok(true));
+        handle.join().unwrap();
+        assert_eq!(stats.saturated(), 0);
+        assert_eq!(stats.compressed(), 2);
+    }
+
+    #[test]
+    fn it_should_send_queued_responses_uncompressed_after_the_timeout() {
+        let value = "a".repeat(1000);
+        let middleware = CompressionMiddleware::with_config(Config::default().concurrency_limit(1, SaturationPolicy::Queue(Duration::from_millis(20))));
+        let stats = middleware.stats();
+        let chain = build_middleware_echo_chain(middleware);
+
+        let streaming = post_data_with_raw_accept_encoding(&value, "br", &chain);
+        let timed_out = post_data_with_raw_accept_encoding(&value, "br", &chain);
+        assert!(is_compressed(&streaming));
+        assert!(!is_compressed(&timed_out));
+        assert_eq!(response::extract_body_to_string(timed_out), value);
+        assert_eq!(stats.queued(), 1);
+        assert_eq!(stats.saturated(), 1);
+        assert_eq!(stats.skipped_because("saturated"), 1);
+    }
+}
+
+#[cfg(all(test, feature = "gzip"))]
+mod adaptive_level_tests {
+    extern crate iron_test;
+
+    use std::io::Write;
+    use iron::headers::*;
+    use self::iron_test::{response};
+
+    use super::{AdaptiveLevel, CompressionEncoding, CompressionMiddleware, Config, OversizePolicy};
+    use super::codec::{encode_with_level, Encoder};
+    use super::test_common::*;
+
+    #[test]
+    fn it_should_compress_using_the_given_level() {
+        let value = "Na".repeat(5000);
+        let compress = |level| {
+            let mut encoder = Encoder::with_level(CompressionEncoding::Gzip, level, Vec::new()).unwrap();
+            encoder.write_all(value.as_bytes()).unwrap();
+            encoder.finish().unwrap()
+        };
+
+        let stored = compress(0);
+        let compressed = compress(9);
+        assert!(stored.len() > value.len());
+        assert!(compressed.len() < value.len() / 10);
+        assert_eq!(gunzip(&stored), value.as_bytes());
+        assert_eq!(gunzip(&compressed), value.as_bytes());
+    }
+
+    #[test]
+    fn it_should_compress_level_1_and_level_9_to_different_sizes() {
+        let words = ["iron", "pack", "gzip", "deflate", "brotli", "level", "window", "huffman"];
+        let value: String = (0..20_000u32).map(|i| words[(i * i % 7 + i % 3) as usize % words.len()]).collect::<Vec<_>>().join(" ");
+        let streamed = |encoding, level| {
+            let mut encoder = Encoder::with_level(encoding, level, Vec::new()).unwrap();
+            encoder.write_all(value.as_bytes()).unwrap();
+            encoder.finish().unwrap()
+        };
+
+        let fastest = streamed(CompressionEncoding::Gzip, 1);
+        let best = streamed(CompressionEncoding::Gzip, 9);
+        assert!(best.len() < fastest.len());
+        assert_eq!(gunzip(&fastest), value.as_bytes());
+        assert_eq!(gunzip(&best), value.as_bytes());
+        assert!(encode_with_level(CompressionEncoding::Gzip, 9, value.as_bytes()).unwrap().len() <
+                encode_with_level(CompressionEncoding::Gzip, 1, value.as_bytes()).unwrap().len());
+        #[cfg(feature = "deflate")]
+        assert!(streamed(CompressionEncoding::Deflate, 9).len() < streamed(CompressionEncoding::Deflate, 1).len());
+    }
+
+    #[test]
+    fn it_should_compress_with_an_adaptive_level_and_measure_the_throughput() {
+        let value = "a".repeat(100_000);
+        let config = Config::default().adaptive_level(AdaptiveLevel::default().large_body(1000));
+        let middleware = CompressionMiddleware::with_config(config);
+        let stats = middleware.stats();
+        let chain = build_middleware_echo_chain(middleware);
+        let res = post_data_with_raw_accept_encoding(&value, "gzip", &chain);
+
+        assert_eq!(res.headers.get::<ContentEncoding>(), Some(&ContentEncoding(vec![Encoding::Gzip])));
+        assert_eq!(stats.in_flight(), 1);
+        let compressed_bytes = response::extract_body_to_bytes(res);
+        assert_eq!(gunzip(&compressed_bytes), value.into_bytes());
+        assert_eq!(stats.in_flight(), 0);
+        assert!(stats.throughput().is_some());
+    }
+
+    #[test]
+    #[cfg(feature = "brotli")]
+    fn it_should_compress_oversize_responses_using_the_fastest_level() {
+        let value: String = (0..20_000u32).map(|i| (b'a' + (i * i % 26) as u8) as char).collect();
+        let chain = build_configured_echo_chain(Config::default()
+            .max_bytes(10_000, OversizePolicy::Fastest)
+            .adaptive_level(AdaptiveLevel::default()));
+        let res = post_data_with_raw_accept_encoding(&value, "br", &chain);
+
+        let compressed_bytes = response::extract_body_to_bytes(res);
+        assert_eq!(compressed_bytes, encode_with_level(CompressionEncoding::Brotli, 1, value.as_bytes()).unwrap());
+        assert!(compressed_bytes != encode_with_level(CompressionEncoding::Brotli, 8, value.as_bytes()).unwrap());
+    }
+}
+
+#[cfg(test)]
+mod cross_site_tests {
+    use iron::headers::{Headers, Host};
+
+    use super::breach::is_cross_site;
+    #[cfg(feature = "padding")]
+    use super::breach::padding;
+
+    fn headers(raw: &[(&str, &str)]) -> Headers {
+        let mut headers = Headers::new();
+        for &(name, value) in raw {
+            headers.set_raw(name.to_owned(), vec![value.as_bytes().to_vec()]);
+        }
+        headers
+    }
+
+    #[test]
+    fn it_should_decide_on_sec_fetch_site() {
+        assert!(is_cross_site(&headers(&[("Sec-Fetch-Site", "cross-site")])));
+        assert!(!is_cross_site(&headers(&[("Sec-Fetch-Site", "same-origin")])));
+        assert!(!is_cross_site(&headers(&[("Sec-Fetch-Site", "none"), ("Origin", "https://evil.example")])));
+    }
+
+    #[test]
+    fn it_should_compare_the_origin_to_the_host() {
+        let mut same = headers(&[("Origin", "https://example.com:8443")]);
+        same.set(Host { hostname: "example.com".to_owned(), port: Some(8443) });
+        assert!(!is_cross_site(&same));
+
+        let mut cross = headers(&[("Origin", "https://evil.example")]);
+        cross.set(Host { hostname: "example.com".to_owned(), port: None });
+        assert!(is_cross_site(&cross));
+
+        let mut opaque = headers(&[("Origin", "null")]);
+        opaque.set(Host { hostname: "example.com".to_owned(), port: None });
+        assert!(is_cross_site(&opaque));
+    }
+
+    #[test]
+    fn it_should_count_requests_without_site_headers_as_same_site() {
+        assert!(!is_cross_site(&Headers::new()));
+    }
+
+    #[test]
+    #[cfg(feature = "padding")]
+    fn it_should_pad_with_a_bounded_number_of_printable_characters() {
+        for _ in 0..100 {
+            let padding = padding(20);
+            assert!(padding.len() <= 20);
+            assert!(padding.iter().all(|byte| (b'!'..=b'~').contains(byte)));
+        }
+        assert!(padding(0).is_empty());
+    }
+}
+
+#[cfg(all(test, feature = "gzip"))]
+mod breach_tests {
+    extern crate iron_test;
+
+    #[cfg(feature = "padding")]
+    use std::io::Read;
+    use iron::prelude::*;
+    use iron::headers::*;
+    use iron::{Chain, status};
+    use self::iron_test::response;
+    #[cfg(feature = "padding")]
+    use libflate::gzip;
+
+    use super::{BreachMitigation, CompressionMiddleware, Config, Sensitive};
+    #[cfg(all(feature = "padding", feature = "brotli"))]
+    use super::{negotiate_exchange, Decision, Exchange, SkipReason};
+    use super::test_common::*;
+
+    fn post_with_headers(raw: &[(&str, &str)], chain: &Chain) -> Response {
+        let raw: Vec<(&str, &str)> = Some(("Accept-Encoding", "gzip")).into_iter().chain(raw.iter().cloned()).collect();
+        post_data_with_raw_headers("/", &"a".repeat(1000), &raw, chain)
+    }
+
+    #[test]
+    fn it_should_not_compress_cross_site_requests() {
+        let chain = build_configured_echo_chain(Config::default()
+            .debug_header(true)
+            .breach_mitigation(BreachMitigation::default().skip_cross_site(true)));
+
+        let res = post_with_headers(&[("Sec-Fetch-Site", "cross-site")], &chain);
+        assert_eq!(res.headers.get::<ContentEncoding>(), None);
+        assert_eq!(res.headers.get_raw("X-Compression"), Some(&[b"skipped; reason=cross-site".to_vec()][..]));
+
+        let res = post_with_headers(&[("Origin", "http://evil.example")], &chain);
+        assert_eq!(res.headers.get::<ContentEncoding>(), None);
+
+        let res = post_with_headers(&[("Origin", "http://localhost:3000")], &chain);
+        assert_eq!(res.headers.get::<ContentEncoding>(), Some(&ContentEncoding(vec![Encoding::Gzip])));
+    }
+
+    #[test]
+    fn it_should_not_compress_sensitive_responses() {
+        let mut chain = Chain::new(|_: &mut Request| Ok(Response::with((status::Ok, "a".repeat(1000), Sensitive))));
+        chain.link_after(CompressionMiddleware::with_config(Config::default()
+            .breach_mitigation(BreachMitigation::default().skip_sensitive(true))));
+
+        let res = post_with_headers(&[], &chain);
+        assert_eq!(res.headers.get::<ContentEncoding>(), None);
+        assert_eq!(response::extract_body_to_string(res), "a".repeat(1000));
+    }
+
+    #[test]
+    fn it_should_compress_sensitive_responses_without_mitigation() {
+        let mut chain = Chain::new(|_: &mut Request| Ok(Response::with((status::Ok, "a".repeat(1000), Sensitive))));
+        chain.link_after(CompressionMiddleware::new());
+
+        let res = post_with_headers(&[], &chain);
+        assert_eq!(res.headers.get::<ContentEncoding>(), Some(&ContentEncoding(vec![Encoding::Gzip])));
+    }
+
+    #[test]
+    #[cfg(feature = "padding")]
+    fn it_should_pad_gzip_responses_with_a_random_header_comment() {
+        let value = "a".repeat(1000);
+        let chain = build_configured_echo_chain(Config::default()
+            .breach_mitigation(BreachMitigation::default().padding(32)));
+
+        let mut lengths = Vec::new();
+        for _ in 0..20 {
+            let res = post_data_with_raw_accept_encoding(&value, "br, gzip, deflate", &chain);
+            assert_eq!(res.headers.get::<ContentEncoding>(), Some(&ContentEncoding(vec![Encoding::Gzip])));
+
+            let compressed_bytes = response::extract_body_to_bytes(res);
+            let mut decoder = gzip::Decoder::new(&compressed_bytes[..]).unwrap();
+            let comment_length = decoder.header().comment().map_or(0, |comment| comment.as_bytes().len());
+            assert!(comment_length <= 32);
+            let mut decoded_data = Vec::new();
+            decoder.read_to_end(&mut decoded_data).unwrap();
+            assert_eq!(decoded_data, value.as_bytes());
+            lengths.push(compressed_bytes.len());
+        }
+
+        lengths.dedup();
+        assert!(lengths.len() > 1);
+    }
+
+    #[test]
+    #[cfg(all(feature = "padding", feature = "brotli"))]
+    fn it_should_report_clients_that_accept_no_encoding_which_can_be_padded() {
+        let config = Config::default().breach_mitigation(BreachMitigation::default().padding(32));
+        let accept_encoding = [b"br".to_vec()];
+        let exchange = Exchange { accept_encoding: Some(&accept_encoding), has_body: true, content_length: Some(1000), ..Exchange::default() };
+        assert_eq!(negotiate_exchange(&exchange, &config), Decision::Skip(SkipReason::Padding));
+
+        let res = post_data_with_raw_accept_encoding(&"a".repeat(1000), "br", &build_configured_echo_chain(config));
+        assert_eq!(res.headers.get::<ContentEncoding>(), None);
+    }
+}
+
+#[cfg(all(test, feature = "gzip"))]
+mod gzip_header_tests {
+    extern crate iron_test;
+
+    use std::io::Read;
+    use self::iron_test::response;
+    use libflate::gzip;
+
+    use super::Config;
+    use super::codec::{encode_gzip, GzipHeader};
+    use super::test_common::*;
+
+    fn post_gzip(value: &str, config: Config) -> Vec<u8> {
+        let chain = build_configured_echo_chain(config);
+        response::extract_body_to_bytes(post_data_with_raw_accept_encoding(value, "gzip", &chain))
+    }
+
+    #[test]
+    fn it_should_compress_identical_bodies_into_identical_responses() {
+        let value = "a".repeat(1000);
+        let first = post_gzip(&value, Config::default());
+        let second = post_gzip(&value, Config::default());
+        assert_eq!(first, second);
+        assert_eq!(&first[..10], &[0x1f, 0x8b, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xff]);
+    }
+
+    #[test]
+    fn it_should_write_the_configured_header_fields() {
+        let value = "a".repeat(1000);
+        let header = GzipHeader {
+            mtime: 1_500_000_000,
+            os: 3,
+            filename: Some(b"batman.txt".to_vec()),
+            comment: Some(b"na\0na".to_vec()),
+            extra: vec![([b'I', b'P'], b"pack".to_vec())],
+        };
+        let compressed_bytes = post_gzip(&value, Config::default().gzip_header(header));
+
+        let mut decoder = gzip::Decoder::new(&compressed_bytes[..]).unwrap();
+        assert_eq!(decoder.header().modification_time(), 1_500_000_000);
+        assert_eq!(compressed_bytes[9], 3);
+        assert_eq!(decoder.header().filename().map(|name| name.as_bytes()), Some(&b"batman.txt"[..]));
+        assert_eq!(decoder.header().comment().map(|comment| comment.as_bytes()), Some(&b"nana"[..]));
+        let subfield = &decoder.header().extra_field().unwrap().subfields[0];
+        assert_eq!((subfield.id, &subfield.data[..]), ([b'I', b'P'], &b"pack"[..]));
+
+        let mut decoded_data = Vec::new();
+        decoder.read_to_end(&mut decoded_data).unwrap();
+        assert_eq!(decoded_data, value.as_bytes());
+    }
+
+    #[test]
+    fn it_should_write_the_same_header_in_one_go() {
+        let header = GzipHeader { filename: Some(b"batman.txt".to_vec()), ..GzipHeader::default() };
+        let compressed_bytes = encode_gzip(6, &header, b"Batman!").unwrap();
+        assert_eq!(&compressed_bytes[..header.to_bytes().len()], &header.to_bytes()[..]);
+
+        let mut decoded_data = Vec::new();
+        gzip::Decoder::new(&compressed_bytes[..]).unwrap().read_to_end(&mut decoded_data).unwrap();
+        assert_eq!(decoded_data, b"Batman!");
+    }
+}
+
+#[cfg(all(test, feature = "digest"))]
+mod hashers_tests {
+    use super::DigestAlgorithm;
+    use super::digest::Hashers;
+
+    #[test]
+    fn it_should_digest_empty_data() {
+        assert_eq!(Hashers::of(&[DigestAlgorithm::Sha256], b"").header_value(),
+                   "sha-256=:47DEQpj8HBSa+/TImW+5JCeuQeRkm5NMpJWZG3hSuFU=:");
+    }
+
+    #[test]
+    fn it_should_list_several_algorithms_in_order() {
+        let value = Hashers::of(&[DigestAlgorithm::Sha512, DigestAlgorithm::Sha256], b"hello");
+        assert_eq!(value.header_value(),
+                   "sha-512=:m3HSJL1i83hdltRq0+o9czGb+8KJDKra4t/3JRlnPKcjI8PZm6XBHXx6zG4UuMXaDEZjR1wuXDre9G9zvN7AQw==:, \
+                    sha-256=:LPJNul+wow4m6DsqxbninhsWHlwfp0JecwQzYpOLmCQ=:");
+    }
+
+    #[test]
+    fn it_should_digest_incremental_updates() {
+        let mut hashers = Hashers::new(&[DigestAlgorithm::Sha256]);
+        hashers.update(b"hel");
+        hashers.update(b"lo");
+        assert_eq!(hashers.header_value(), Hashers::of(&[DigestAlgorithm::Sha256], b"hello").header_value());
+    }
+}
+
+#[cfg(all(test, feature = "digest", feature = "gzip"))]
+mod digest_tests {
+    extern crate iron_test;
+
+    use iron::headers::*;
+    use self::iron_test::response;
+    use base64::Engine;
+    use base64::engine::general_purpose::STANDARD as BASE64;
+    use sha2::{Digest, Sha256, Sha512};
+
+    use super::{Config, DigestAlgorithm};
+    use super::test_common::*;
+
+    fn digest_header(res: &iron::Response, name: &str) -> Option<String> {
+        res.headers.get_raw(name).map(|values| String::from_utf8(values[0].clone()).unwrap())
+    }
+
+    #[test]
+    fn it_should_digest_the_compressed_representation() {
+        let value = "a".repeat(1000);
+        let chain = build_configured_echo_chain(Config::default().digests(vec![DigestAlgorithm::Sha256]));
+
+        let res = post_data_with_raw_accept_encoding(&value, "gzip", &chain);
+        assert_eq!(res.headers.get::<ContentEncoding>(), Some(&ContentEncoding(vec![Encoding::Gzip])));
+        let repr_digest = digest_header(&res, "Repr-Digest");
+        let content_digest = digest_header(&res, "Content-Digest");
+        let compressed_bytes = response::extract_body_to_bytes(res);
+
+        assert_eq!(content_digest, Some(format!("sha-256=:{}:", BASE64.encode(&Sha256::digest(&compressed_bytes)))));
+        assert_eq!(repr_digest, content_digest);
+    }
+
+    #[test]
+    fn it_should_list_several_algorithms() {
+        let value = "a".repeat(1000);
+        let chain = build_configured_echo_chain(Config::default()
+            .digests(vec![DigestAlgorithm::Sha256, DigestAlgorithm::Sha512]));
+
+        let res = post_data_with_raw_accept_encoding(&value, "gzip", &chain);
+        let repr_digest = digest_header(&res, "Repr-Digest");
+        let compressed_bytes = response::extract_body_to_bytes(res);
+        assert_eq!(repr_digest, Some(format!("sha-256=:{}:, sha-512=:{}:",
+                                             BASE64.encode(&Sha256::digest(&compressed_bytes)),
+                                             BASE64.encode(&Sha512::digest(&compressed_bytes)))));
+    }
+
+    #[test]
+    fn it_should_not_digest_without_configured_algorithms_or_uncompressed_responses() {
+        let chain = build_configured_echo_chain(Config::default());
+        let res = post_data_with_raw_accept_encoding(&"a".repeat(1000), "gzip", &chain);
+        assert_eq!(digest_header(&res, "Content-Digest"), None);
+
+        let chain = build_configured_echo_chain(Config::default().digests(vec![DigestAlgorithm::Sha256]));
+        let res = post_data_with_raw_accept_encoding("Batman!", "gzip", &chain);
+        assert_eq!(res.headers.get::<ContentEncoding>(), None);
+        assert_eq!(digest_header(&res, "Content-Digest"), None);
+    }
+}
+
+#[cfg(all(test, feature = "gzip"))]
+mod transfer_coding_tests {
+    extern crate iron_test;
+
+    use std::io::Read;
+    use iron::headers::*;
+    use iron::Chain;
+    use self::iron_test::response;
+    use libflate::gzip;
+
+    use super::Config;
+    use super::test_common::*;
+
+    fn post_with_headers(raw: &[(&str, &str)], chain: &Chain) -> iron::Response {
+        post_data_with_raw_headers("/", &"a".repeat(1000), raw, chain)
+    }
+
+    #[test]
+    fn it_should_compress_as_transfer_coding_if_the_client_accepts_it() {
+        let chain = build_configured_echo_chain(Config::default().transfer_coding(true));
+
+        let res = post_with_headers(&[("TE", "gzip, trailers"), ("Accept-Encoding", "br")], &chain);
+        assert_eq!(res.headers.get::<TransferEncoding>(), Some(&TransferEncoding(vec![Encoding::Gzip])));
+        assert_eq!(res.headers.get::<ContentEncoding>(), None);
+        assert_eq!(res.headers.get::<ContentLength>(), None);
+
+        let compressed_bytes = response::extract_body_to_bytes(res);
+        let mut decoded_data = Vec::new();
+        gzip::Decoder::new(&compressed_bytes[..]).unwrap().read_to_end(&mut decoded_data).unwrap();
+        assert_eq!(decoded_data, "a".repeat(1000).as_bytes());
+    }
+
+    #[test]
+    fn it_should_not_send_a_content_length_for_buffered_transfer_codings() {
+        let chain = build_configured_echo_chain(Config::default().transfer_coding(true).debug_header(true));
+
+        let res = post_with_headers(&[("TE", "gzip")], &chain);
+        assert_eq!(res.headers.get::<TransferEncoding>(), Some(&TransferEncoding(vec![Encoding::Gzip])));
+        assert_eq!(res.headers.get::<ContentLength>(), None);
+        assert!(res.headers.get_raw("X-Compression").is_some());
+    }
+
+    #[test]
+    fn it_should_fall_back_to_content_codings() {
+        let chain = build_configured_echo_chain(Config::default().transfer_coding(true));
+
+        let res = post_with_headers(&[("TE", "trailers"), ("Accept-Encoding", "gzip")], &chain);
+        assert_eq!(res.headers.get::<TransferEncoding>(), None);
+        assert_eq!(res.headers.get::<ContentEncoding>(), Some(&ContentEncoding(vec![Encoding::Gzip])));
+    }
+
+    #[test]
+    fn it_should_ignore_the_te_header_by_default() {
+        let chain = build_configured_echo_chain(Config::default());
+
+        let res = post_with_headers(&[("TE", "gzip")], &chain);
+        assert_eq!(res.headers.get::<TransferEncoding>(), None);
+        assert_eq!(res.headers.get::<ContentEncoding>(), None);
+    }
+}
+
+#[cfg(all(test, feature = "gzip", feature = "deflate", feature = "brotli"))]
+mod transcoding_tests {
+    extern crate iron_test;
+
+    use iron::prelude::*;
+    use iron::headers::*;
+    use iron::{Chain, status};
+    use self::iron_test::response;
+
+    use super::{CompressionEncoding, CompressionMiddleware, Config};
+    use super::codec::{decode, encode};
+    use super::test_common::*;
+
+    fn build_gzipped_chain(config: Config) -> Chain {
+        let mut chain = Chain::new(|_: &mut Request| {
+            let gzipped = encode(CompressionEncoding::Gzip, "a".repeat(1000).as_bytes()).unwrap();
+            let mut res = Response::with((status::Ok, gzipped));
+            res.headers.set(ContentEncoding(vec![Encoding::Gzip]));
+            Ok(res)
+        });
+        chain.link_after(CompressionMiddleware::with_config(config));
+        chain
+    }
+
+    fn get_with_accept_encoding(accept_encoding: &str, chain: &Chain) -> Response {
+        get_with_raw_accept_encoding("/", accept_encoding, chain)
+    }
+
+    #[test]
+    fn it_should_decode_round_trips_of_every_encoding() {
+        for encoding in CompressionEncoding::all() {
+            let value = "Na".repeat(1000);
+            assert_eq!(decode(encoding, &encode(encoding, value.as_bytes()).unwrap()).unwrap(), value.as_bytes());
+        }
+    }
+
+    #[test]
+    fn it_should_transcode_to_an_encoding_the_client_accepts() {
+        let chain = build_gzipped_chain(Config::default().transcode(true).min_bytes(10));
+
+        let res = get_with_accept_encoding("br", &chain);
+        assert_eq!(res.headers.get::<ContentEncoding>(), Some(&ContentEncoding(vec![Encoding::EncodingExt(String::from("br"))])));
+        let compressed_bytes = response::extract_body_to_bytes(res);
+        assert_eq!(decode(CompressionEncoding::Brotli, &compressed_bytes).unwrap(), "a".repeat(1000).as_bytes());
+    }
+
+    #[test]
+    fn it_should_transcode_bodies_larger_than_the_copy_buffer_without_their_encoded_length() {
+        let value = (0..20000).map(|number| number.to_string()).collect::<Vec<_>>().join(",");
+        let gzipped = encode(CompressionEncoding::Gzip, value.as_bytes()).unwrap();
+        assert!(gzipped.len() > 8 * 1024);
+        let mut chain = Chain::new(move |_: &mut Request| {
+            let mut res = Response::with((status::Ok, gzipped.clone()));
+            res.headers.set(ContentEncoding(vec![Encoding::Gzip]));
+            Ok(res)
+        });
+        chain.link_after(CompressionMiddleware::with_config(Config::default().transcode(true).debug_header(true)));
+
+        let res = get_with_accept_encoding("deflate", &chain);
+        assert_eq!(res.headers.get::<ContentEncoding>(), Some(&ContentEncoding(vec![Encoding::Deflate])));
+        assert_eq!(res.headers.get_raw("X-Compression"), Some(&[b"deflate".to_vec()][..]));
+        let compressed_bytes = response::extract_body_to_bytes(res);
+        assert_eq!(decode(CompressionEncoding::Deflate, &compressed_bytes).unwrap(), value.as_bytes());
+    }
+
+    #[test]
+    fn it_should_send_decoded_bodies_if_the_client_accepts_no_encoding() {
+        let chain = build_gzipped_chain(Config::default().transcode(true));
+
+        let res = get_with_accept_encoding("identity", &chain);
+        assert_eq!(res.headers.get::<ContentEncoding>(), None);
+        assert_eq!(response::extract_body_to_string(res), "a".repeat(1000));
+    }
+
+    #[test]
+    fn it_should_keep_encodings_the_client_accepts() {
+        let chain = build_gzipped_chain(Config::default().transcode(true));
+
+        let res = get_with_accept_encoding("br, gzip;q=0.5", &chain);
+        assert_eq!(res.headers.get::<ContentEncoding>(), Some(&ContentEncoding(vec![Encoding::Gzip])));
+        let compressed_bytes = response::extract_body_to_bytes(res);
+        assert_eq!(compressed_bytes, encode(CompressionEncoding::Gzip, "a".repeat(1000).as_bytes()).unwrap());
+    }
+
+    #[test]
+    fn it_should_not_transcode_by_default() {
+        let chain = build_gzipped_chain(Config::default());
+
+        let res = get_with_accept_encoding("br", &chain);
+        assert_eq!(res.headers.get::<ContentEncoding>(), Some(&ContentEncoding(vec![Encoding::Gzip])));
+    }
+}
+
+#[cfg(all(test, feature = "precompress"))]
+mod content_type_tests {
+    use super::content_type::is_compressible;
+
+    fn compressible(mime: &str) -> bool {
+        is_compressible(&mime.parse().unwrap())
+    }
+
+    #[test]
+    fn it_should_compress_text_and_structured_types() {
+        assert!(compressible("text/html; charset=utf-8"));
+        assert!(compressible("application/json"));
+        assert!(compressible("application/javascript"));
+        assert!(compressible("application/ld+json"));
+        assert!(compressible("image/svg+xml"));
+        assert!(compressible("application/wasm"));
+        assert!(compressible("font/ttf"));
+    }
+
+    #[test]
+    fn it_should_not_compress_compressed_types() {
+        assert!(!compressible("image/png"));
+        assert!(!compressible("image/jpeg"));
+        assert!(!compressible("video/mp4"));
+        assert!(!compressible("font/woff2"));
+        assert!(!compressible("application/zip"));
+        assert!(!compressible("application/octet-stream"));
+    }
+}
+
+#[cfg(all(test, feature = "gzip", feature = "brotli"))]
+mod static_files_tests {
+    extern crate iron_test;
+
+    use std::path::PathBuf;
+    use iron::headers::*;
+    use iron::status;
+    use self::iron_test::{request, response};
+
+    use super::{CompressionEncoding, CompressionMiddleware, Config, Rule, StaticFiles};
+    use super::codec::{decode, encode};
+    use super::test_common::*;
+
+    fn public_dir(name: &str, files: &[(&str, &[u8])]) -> PathBuf {
+        temp_dir(&format!("static-{}", name), files)
+    }
+
+    #[test]
+    fn it_should_serve_the_sidecar_the_client_prefers() {
+        let value = "a".repeat(1000);
+        let dir = public_dir("prefers", &[("style.css", value.as_bytes()), ("style.css.br", b"brotli"), ("style.css.gz", b"gzip!"), ("style.css.zst", b"zstd")]);
+        let handler = StaticFiles::new(dir);
+
+        let res = get_with_raw_accept_encoding("/style.css", "gzip, br;q=0.5", &handler);
+        assert_eq!(content_encoding(&res), Some(String::from("gzip")));
+        assert_eq!(res.headers.get::<ContentType>().map(|content_type| content_type.to_string()), Some(String::from("text/css")));
+        assert_eq!(res.headers.get::<ContentLength>(), Some(&ContentLength(5)));
+        assert_eq!(res.headers.get_raw("Vary"), Some(&[b"Accept-Encoding".to_vec()][..]));
+        assert_eq!(response::extract_body_to_string(res), "gzip!");
+    }
+
+    #[test]
+    fn it_should_serve_zstd_sidecars_with_the_zstd_feature() {
+        let value = "a".repeat(1000);
+        let dir = public_dir("zstd", &[("style.css", value.as_bytes()), ("style.css.zst", b"zstd")]);
+        let handler = StaticFiles::new(dir);
+
+        let res = get_with_raw_accept_encoding("/style.css", "zstd", &handler);
+        if cfg!(feature = "zstd") {
+            assert_eq!(content_encoding(&res), Some(String::from("zstd")));
+            assert_eq!(response::extract_body_to_string(res), "zstd");
+        } else {
+            assert_eq!(content_encoding(&res), None);
+            assert_eq!(response::extract_body_to_string(res), value);
+        }
+    }
+
+    #[test]
+    fn it_should_choose_sidecars_by_the_configured_priority() {
+        let value = "a".repeat(1000);
+        let files: &[(&str, &[u8])] = &[("app.js", value.as_bytes()), ("app.js.br", b"brotli"), ("app.js.gz", b"gzip!")];
+
+        let handler = StaticFiles::new(public_dir("priority", files));
+        let res = get_with_raw_accept_encoding("/app.js", "gzip, br", &handler);
+        assert_eq!(content_encoding(&res), Some(String::from("br")));
+        assert_eq!(response::extract_body_to_string(res), "brotli");
+
+        let config = Config::default().priority(vec![CompressionEncoding::Gzip, CompressionEncoding::Brotli]);
+        let handler = StaticFiles::with_config(public_dir("priority-gzip", files), config);
+        let res = get_with_raw_accept_encoding("/app.js", "gzip, br", &handler);
+        assert_eq!(content_encoding(&res), Some(String::from("gzip")));
+        assert_eq!(response::extract_body_to_string(res), "gzip!");
+    }
+
+    #[test]
+    fn it_should_not_serve_sidecars_of_files_that_are_too_small() {
+        let dir = public_dir("too-small", &[("app.js", b"Batman!"), ("app.js.gz", b"gzip!")]);
+        let handler = StaticFiles::new(dir);
+
+        let res = get_with_raw_accept_encoding("/app.js", "gzip", &handler);
+        assert_eq!(content_encoding(&res), None);
+        assert_eq!(response::extract_body_to_string(res), "Batman!");
+    }
+
+    #[test]
+    fn it_should_not_serve_sidecars_directly() {
+        let dir = public_dir("direct", &[("app.js", b"Batman!"), ("app.js.br", b"brotli"), ("app.js.zst", b"zstd"), ("backup.tar.gz", b"archive")]);
+        let handler = StaticFiles::new(dir);
+
+        for path in &["/app.js.br", "/app.js.zst"] {
+            let err = request::get(&format!("http://localhost:3000{}", path), Headers::new(), &handler).unwrap_err();
+            assert_eq!(err.response.status, Some(status::NotFound));
+        }
+
+        let res = get_with_raw_accept_encoding("/backup.tar.gz", "identity", &handler);
+        assert_eq!(response::extract_body_to_string(res), "archive");
+    }
+
+    #[test]
+    fn it_should_compress_files_without_acceptable_sidecars_on_the_fly() {
+        let value = "a".repeat(1000);
+        let dir = public_dir("on-the-fly", &[("index.html", value.as_bytes()), ("index.html.br", b"brotli")]);
+        let handler = StaticFiles::new(dir);
+
+        let res = get_with_raw_accept_encoding("/", "gzip", &handler);
+        assert_eq!(content_encoding(&res), Some(String::from("gzip")));
+        assert_eq!(res.headers.get_raw("Vary"), Some(&[b"Accept-Encoding".to_vec()][..]));
+        let compressed_bytes = response::extract_body_to_bytes(res);
+        assert_eq!(decode(CompressionEncoding::Gzip, &compressed_bytes).unwrap(), value.as_bytes());
+
+        let res = get_with_raw_accept_encoding("/index.html", "identity", &handler);
+        assert_eq!(content_encoding(&res), None);
+        assert_eq!(response::extract_body_to_string(res), value);
+    }
+
+    #[test]
+    fn it_should_serve_real_sidecars_that_decode_to_the_original() {
+        let value = "a".repeat(1000);
+        let gzipped = encode(CompressionEncoding::Gzip, value.as_bytes()).unwrap();
+        let dir = public_dir("real", &[("data.json", value.as_bytes()), ("data.json.gz", &gzipped)]);
+        let handler = StaticFiles::new(dir);
+
+        let res = get_with_raw_accept_encoding("/data.json", "gzip", &handler);
+        assert_eq!(res.headers.get::<ContentType>().map(|content_type| content_type.to_string()), Some(String::from("application/json")));
+        assert_eq!(response::extract_body_to_bytes(res), gzipped);
+    }
+
+    #[test]
+    fn it_should_not_find_missing_files() {
+        let dir = public_dir("missing", &[("index.html", b"Batman!")]);
+        let handler = StaticFiles::new(dir);
+
+        let mut headers = Headers::new();
+        headers.set_raw("Accept-Encoding", vec![b"gzip".to_vec()]);
+        let err = request::get("http://localhost:3000/robin.html", headers, &handler).unwrap_err();
+        assert_eq!(err.response.status, Some(status::NotFound));
+    }
+
+    #[test]
+    fn it_should_answer_matching_validators_with_not_modified() {
+        let dir = public_dir("not-modified", &[("app.js", b"Batman!"), ("app.js.gz", b"gzip!")]);
+        let handler = StaticFiles::new(dir);
+
+        let res = get_with_raw_accept_encoding("/app.js", "gzip", &handler);
+        let tag = res.headers.get::<ETag>().cloned().unwrap();
+        assert!(tag.0.weak);
+
+        let mut headers = Headers::new();
+        headers.set_raw("Accept-Encoding", vec![b"identity".to_vec()]);
+        headers.set(IfNoneMatch::Items(vec![tag.0.clone()]));
+        let res = request::get("http://localhost:3000/app.js", headers, &handler).unwrap();
+        assert_eq!(res.status, Some(status::NotModified));
+        assert_eq!(res.headers.get::<ETag>(), Some(&tag));
+        assert_eq!(response::extract_body_to_string(res), "");
+
+        let mut headers = Headers::new();
+        headers.set(IfNoneMatch::Items(vec![EntityTag::weak(String::from("robin"))]));
+        let res = request::get("http://localhost:3000/app.js", headers, &handler).unwrap();
+        assert_eq!(res.status, Some(status::Ok));
+    }
+
+    #[test]
+    fn it_should_send_the_configured_cache_control() {
+        let dir = public_dir("cache-control", &[("index.html", b"Batman!")]);
+        let handler = StaticFiles::new(dir).cache_control(vec![CacheDirective::Public, CacheDirective::MaxAge(60)]);
+
+        let res = get_with_raw_accept_encoding("/", "gzip", &handler);
+        assert_eq!(res.headers.get::<CacheControl>(), Some(&CacheControl(vec![CacheDirective::Public, CacheDirective::MaxAge(60)])));
+    }
+
+    #[test]
+    fn it_should_apply_the_rules_of_the_middleware() {
+        let value = "a".repeat(1000);
+        let dir = public_dir("rules", &[("index.html", value.as_bytes()), ("index.html.gz", b"gzip!")]);
+        let handler = StaticFiles::with_middleware(dir, CompressionMiddleware::new().rule("/**", Rule::never()));
+
+        let res = get_with_raw_accept_encoding("/index.html", "gzip", &handler);
+        assert_eq!(content_encoding(&res), None);
+        assert_eq!(response::extract_body_to_string(res), value);
+    }
+
+    #[test]
+    fn it_should_apply_the_predicates_of_the_middleware() {
+        let value = "a".repeat(1000);
+        let dir = public_dir("predicates", &[("index.html", value.as_bytes()), ("index.html.gz", b"gzip!")]);
+        let middleware = CompressionMiddleware::new().exclude("html", |_, res| {
+            res.headers.get::<ContentType>().map_or(false, |content_type| content_type.to_string() == "text/html")
+        });
+        let handler = StaticFiles::with_middleware(dir, middleware);
+
+        let res = get_with_raw_accept_encoding("/index.html", "gzip", &handler);
+        assert_eq!(content_encoding(&res), None);
+        assert_eq!(response::extract_body_to_string(res), value);
+    }
+}
+
+#[cfg(all(test, feature = "embed", feature = "gzip"))]
+mod embed_tests {
+    extern crate iron_test;
+
+    use iron::headers::*;
+    use iron::status;
+    use self::iron_test::{request, response};
+
+    use super::{include_compressed, CompressionEncoding, CompressionMiddleware, Config, EmbeddedAsset, EmbeddedAssets, Rule};
+    use super::codec::{decode, encode_gzip, max_level, GzipHeader};
+    use super::test_common::*;
+
+    const INDEX: EmbeddedAsset = EmbeddedAsset { path: "dist/index.html", identity: b"Batman!", brotli: Some(b"brotli"), gzip: Some(b"gzip!") };
+    const APP: EmbeddedAsset = EmbeddedAsset { path: "dist/app.js", identity: b"Robin!", brotli: None, gzip: Some(b"gzip!") };
+
+    fn build_assets() -> EmbeddedAssets {
+        EmbeddedAssets::with_config(Config::default().min_bytes(0)).asset("/index.html", INDEX).asset("/js/app.js", APP)
+    }
+
+    #[test]
+    fn it_should_serve_the_variant_the_client_prefers() {
+        let res = get_with_raw_accept_encoding("/index.html", "gzip, br;q=0.5", &build_assets());
+        assert_eq!(content_encoding(&res), Some(String::from("gzip")));
+        assert_eq!(res.headers.get::<ContentType>().map(|content_type| content_type.to_string()), Some(String::from("text/html")));
+        assert_eq!(res.headers.get_raw("Vary"), Some(&[b"Accept-Encoding".to_vec()][..]));
+        assert_eq!(response::extract_body_to_string(res), "gzip!");
+
+        let res = get_with_raw_accept_encoding("/index.html", "br", &build_assets());
+        assert_eq!(content_encoding(&res), Some(String::from("br")));
+        assert_eq!(response::extract_body_to_string(res), "brotli");
+    }
+
+    #[cfg(feature = "brotli")]
+    #[test]
+    fn it_should_break_ties_using_the_configured_priority() {
+        let res = get_with_raw_accept_encoding("/index.html", "gzip, br", &build_assets());
+        assert_eq!(content_encoding(&res), Some(String::from("br")));
+
+        let config = Config::default().min_bytes(0).priority(vec![CompressionEncoding::Gzip, CompressionEncoding::Brotli]);
+        let assets = EmbeddedAssets::with_config(config).asset("/index.html", INDEX);
+        let res = get_with_raw_accept_encoding("/index.html", "gzip, br", &assets);
+        assert_eq!(content_encoding(&res), Some(String::from("gzip")));
+    }
+
+    #[test]
+    fn it_should_apply_the_rules_of_the_middleware() {
+        let middleware = CompressionMiddleware::with_config(Config::default().min_bytes(0)).rule("/**", Rule::never());
+        let assets = EmbeddedAssets::with_middleware(middleware).asset("/index.html", INDEX);
+        let res = get_with_raw_accept_encoding("/index.html", "gzip, br", &assets);
+        assert_eq!(content_encoding(&res), None);
+        assert_eq!(response::extract_body_to_string(res), "Batman!");
+    }
+
+    #[test]
+    fn it_should_serve_the_identity_variant_of_small_assets() {
+        let assets = EmbeddedAssets::new().asset("/index.html", INDEX);
+        let res = get_with_raw_accept_encoding("/index.html", "gzip, br", &assets);
+        assert_eq!(content_encoding(&res), None);
+    }
+
+    #[test]
+    fn it_should_serve_the_identity_variant_to_other_clients() {
+        let res = get_with_raw_accept_encoding("/js/app.js", "br", &build_assets());
+        assert_eq!(content_encoding(&res), None);
+        assert_eq!(res.headers.get::<ContentType>().map(|content_type| content_type.to_string()), Some(String::from("application/javascript")));
+        assert_eq!(response::extract_body_to_string(res), "Robin!");
+    }
+
+    #[test]
+    fn it_should_serve_the_index_of_directories() {
+        let res = get_with_raw_accept_encoding("/", "identity", &build_assets());
+        assert_eq!(response::extract_body_to_string(res), "Batman!");
+    }
+
+    #[test]
+    fn it_should_not_find_missing_assets() {
+        let err = request::get("http://localhost:3000/robin.html", Headers::new(), &build_assets()).unwrap_err();
+        assert_eq!(err.response.status, Some(status::NotFound));
+    }
+
+    #[test]
+    fn it_should_embed_the_variants_the_codec_produces() {
+        const README: EmbeddedAsset = include_compressed!("README.md");
+        let readme = &include_bytes!("../README.md")[..];
+
+        assert_eq!(README.path, "README.md");
+        assert_eq!(README.identity, readme);
+        let gzip = README.gzip.unwrap();
+        assert_eq!(gzip, &encode_gzip(max_level(CompressionEncoding::Gzip), &GzipHeader::default(), readme).unwrap()[..]);
+        assert_eq!(decode(CompressionEncoding::Gzip, gzip).unwrap(), readme);
+        #[cfg(feature = "brotli")]
+        assert_eq!(decode(CompressionEncoding::Brotli, README.brotli.unwrap()).unwrap(), readme);
+    }
+}
+
+#[cfg(all(test, feature = "testing", feature = "gzip", feature = "brotli"))]
+mod testing_tests {
+    use iron::headers::*;
+
+    use super::{CompressionEncoding, CompressionMiddleware, Config};
+    use super::testing::*;
+
+    #[test]
+    fn it_should_decode_every_supported_encoding() {
+        let value = "a".repeat(1000);
+        let chain = compressed_echo_chain(CompressionMiddleware::new());
+
+        for encoding in CompressionEncoding::all() {
+            let res = post_with_accept_encoding(&value, encoding.content_coding(), &chain).unwrap();
+            assert_encoded(&res, encoding);
+            assert_eq!(decode_body(res), value.as_bytes());
+        }
+    }
+
+    #[test]
+    fn it_should_pass_through_identity_bodies() {
+        let chain = compressed_echo_chain(CompressionMiddleware::with_config(Config::default().min_bytes(2000)));
+
+        let res = post_with_accept_encoding("Batman!", "gzip", &chain).unwrap();
+        assert_not_encoded(&res);
+        assert_eq!(decode_body(res), b"Batman!");
+    }
+
+    #[test]
+    #[should_panic(expected = "expected a response encoded using br")]
+    fn it_should_fail_on_unexpected_encodings() {
+        let chain = compressed_echo_chain(CompressionMiddleware::new());
+
+        let res = post_with_accept_encoding(&"a".repeat(1000), "gzip", &chain).unwrap();
+        assert_encoded(&res, CompressionEncoding::Brotli);
+    }
+
+    #[test]
+    #[should_panic(expected = "expected a response that is not encoded")]
+    fn it_should_fail_on_unexpected_compression() {
+        let chain = compressed_echo_chain(CompressionMiddleware::new());
+
+        let res = post_with_accept_encoding(&"a".repeat(1000), "gzip", &chain).unwrap();
+        assert_not_encoded(&res);
+    }
+
+    #[test]
+    fn it_should_build_accept_encoding_headers() {
+        let headers = accept_encoding(&[CompressionEncoding::Brotli, CompressionEncoding::Gzip]);
+        assert_eq!(headers.get::<AcceptEncoding>(),
+                   Some(&AcceptEncoding(vec![qitem(Encoding::EncodingExt(String::from("br"))), qitem(Encoding::Gzip)])));
+
+        let headers = raw_accept_encoding("gzip;q=0.5, br");
+        assert_eq!(headers.get_raw("Accept-Encoding"), Some(&[b"gzip;q=0.5, br".to_vec()][..]));
+    }
+}
+
+#[cfg(all(test, feature = "precompress", feature = "gzip", feature = "brotli"))]
+mod precompress_tests {
+    use std::fs;
+    use std::path::PathBuf;
+
+    use super::{CompressionEncoding, Config, RuleLevel};
+    use super::codec::decode;
+    use super::precompress::{Precompress, SidecarFormat};
+    use super::test_common::*;
+
+    fn build_dir(name: &str, files: &[(&str, &[u8])]) -> PathBuf {
+        temp_dir(&format!("precompress-{}", name), files)
+    }
+
+    fn text(length: usize) -> Vec<u8> {
+        "I am Batman! ".bytes().cycle().take(length).collect()
+    }
+
+    fn noise(length: usize) -> Vec<u8> {
+        let mut state: u32 = 42;
+        (0..length).map(|_| {
+            state = state.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+            (state >> 16) as u8
+        }).collect()
+    }
+
+    #[test]
+    fn it_should_write_decodable_sidecars() {
+        let content = text(4096);
+        let dir = build_dir("decodable", &[("index.html", &content), ("js/app.js", &content)]);
+
+        let precompress = Precompress { formats: vec![SidecarFormat::Brotli, SidecarFormat::Gzip], ..Precompress::default() };
+        let reports = precompress.dir(&dir).unwrap();
+        assert_eq!(reports.len(), 2);
+        assert!(reports.iter().all(|report| report.outcome.as_ref().unwrap().iter().all(|sidecar| sidecar.written)));
+
+        for file in &["index.html", "js/app.js"] {
+            let brotli = fs::read(dir.join(format!("{}.br", file))).unwrap();
+            assert_eq!(decode(CompressionEncoding::Brotli, &brotli).unwrap(), content);
+            let gzip = fs::read(dir.join(format!("{}.gz", file))).unwrap();
+            assert_eq!(decode(CompressionEncoding::Gzip, &gzip).unwrap(), content);
+        }
+    }
+
+    #[test]
+    fn it_should_not_compress_sidecars_again() {
+        let dir = build_dir("again", &[("index.html", &text(4096))]);
+
+        let precompress = Precompress::default();
+        precompress.dir(&dir).unwrap();
+        let reports = precompress.dir(&dir).unwrap();
+        assert_eq!(reports.len(), 1);
+        assert!(!dir.join("index.html.gz.gz").exists());
+    }
+
+    #[test]
+    fn it_should_produce_identical_sidecars_for_unchanged_files() {
+        let dir = build_dir("identical", &[("index.html", &text(4096))]);
+
+        let precompress = Precompress::default();
+        precompress.dir(&dir).unwrap();
+        let first = fs::read(dir.join("index.html.gz")).unwrap();
+        precompress.dir(&dir).unwrap();
+        assert_eq!(fs::read(dir.join("index.html.gz")).unwrap(), first);
+    }
+
+    #[test]
+    fn it_should_skip_small_files_and_compressed_types() {
+        let dir = build_dir("skip", &[("small.html", b"Batman!"), ("logo.png", &text(4096))]);
+
+        let reports = Precompress::default().dir(&dir).unwrap();
+        let outcomes: Vec<_> = reports.iter().map(|report| report.outcome.as_ref().err().cloned()).collect();
+        assert_eq!(outcomes, vec![Some("content-type"), Some("too-small")]);
+        assert!(!dir.join("logo.png.gz").exists());
+        assert!(!dir.join("small.html.gz").exists());
+    }
+
+    #[test]
+    fn it_should_compress_any_type_when_asked() {
+        let dir = build_dir("all-types", &[("logo.png", &text(4096))]);
+
+        Precompress { all_types: true, ..Precompress::default() }.dir(&dir).unwrap();
+        assert!(dir.join("logo.png.gz").exists());
+    }
+
+    #[test]
+    fn it_should_not_write_sidecars_that_do_not_pay_off() {
+        let dir = build_dir("pay-off", &[("random.txt", &noise(4096))]);
+        fs::write(dir.join("random.txt.gz"), b"stale").unwrap();
+
+        let reports = Precompress::default().dir(&dir).unwrap();
+        assert!(reports[0].outcome.as_ref().unwrap().iter().all(|sidecar| !sidecar.written));
+        assert!(!dir.join("random.txt.br").exists());
+        assert!(!dir.join("random.txt.gz").exists());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn it_should_not_follow_symbolic_links() {
+        use std::os::unix::fs::symlink;
+
+        let dir = build_dir("symlinks", &[("public/index.html", &text(4096))]);
+        symlink(&dir, dir.join("public/loop")).unwrap();
+        symlink(dir.join("public/index.html"), dir.join("public/link.html")).unwrap();
+
+        let reports = Precompress::default().dir(&dir.join("public")).unwrap();
+        assert_eq!(reports.len(), 1);
+        assert!(dir.join("public/index.html.gz").exists());
+        assert!(!dir.join("public/link.html.gz").exists());
+    }
+
+    #[test]
+    fn it_should_skip_files_smaller_than_the_minimum_of_the_middleware() {
+        assert_eq!(Precompress::default().min_bytes, Config::default().min_bytes);
+    }
+
+    #[test]
+    fn it_should_write_decodable_sidecars_with_max_effort() {
+        let content = text(4096);
+        let dir = build_dir("max-effort", &[("index.html", &content)]);
+
+        Precompress { level: RuleLevel::Max, ..Precompress::default() }.dir(&dir).unwrap();
+        let brotli = fs::read(dir.join("index.html.br")).unwrap();
+        assert_eq!(decode(CompressionEncoding::Brotli, &brotli).unwrap(), content);
+        let gzip = fs::read(dir.join("index.html.gz")).unwrap();
+        assert_eq!(decode(CompressionEncoding::Gzip, &gzip).unwrap(), content);
+    }
+}
+
+#[cfg(all(test, feature = "analysis"))]
+mod analysis_tests {
+    use super::CompressionEncoding;
+    use super::analysis::Analysis;
+    use super::codec;
+    #[cfg(unix)]
+    use super::test_common::*;
+
+    #[test]
+    fn it_should_sample_every_encoding_and_level() {
+        let mut analysis = Analysis::new();
+        analysis.add("text/html", b"I am Batman! I am Batman!").unwrap();
+
+        let reports = analysis.reports();
+        let levels: u32 = CompressionEncoding::all().into_iter().map(codec::max_level).sum();
+        assert_eq!(reports[0].samples.len() as u32, levels);
+    }
+
+    #[test]
+    fn it_should_sum_the_bodies_of_a_content_type() {
+        let mut analysis = Analysis::new();
+        analysis.add("text/html", b"I am Batman!").unwrap();
+        analysis.add("text/html", b"I am Robin!").unwrap();
+        analysis.add("text/css", b"body {}").unwrap();
+
+        let reports = analysis.reports();
+        assert_eq!(reports.iter().map(|report| &report.content_type[..]).collect::<Vec<_>>(), vec!["text/css", "text/html"]);
+        assert_eq!(reports[1].bodies, 2);
+        assert_eq!(reports[1].length, 23);
+    }
+
+    #[test]
+    fn it_should_write_json() {
+        let mut analysis = Analysis::new();
+        analysis.add("text/\"plain\"", b"I am Batman!").unwrap();
+
+        let json = analysis.to_json();
+        assert!(json.starts_with("[{\"content_type\":\"text/\\\"plain\\\"\",\"bodies\":1,\"bytes\":12,\"samples\":[{\"encoding\":"));
+        assert!(json.ends_with("}]}]"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn it_should_not_follow_symbolic_links_below_directories() {
+        use std::os::unix::fs::symlink;
+
+        let dir = temp_dir("analysis-symlinks", &[("bodies/batman.html", b"I am Batman!")]);
+        symlink(dir.join("bodies"), dir.join("bodies/loop")).unwrap();
+        symlink(dir.join("bodies/batman.html"), dir.join("bodies/robin.html")).unwrap();
+
+        let mut analysis = Analysis::new();
+        analysis.add_path(&dir.join("bodies"), None).unwrap();
+        let reports = analysis.reports();
+        assert_eq!(reports.len(), 1);
+        assert_eq!(reports[0].bodies, 1);
+
+        let mut analysis = Analysis::new();
+        analysis.add_path(&dir.join("bodies/robin.html"), None).unwrap();
+        assert_eq!(analysis.reports()[0].bodies, 1);
+    }
+}
+
+#[cfg(all(test, feature = "gzip"))]
+mod predicate_tests {
+    use iron::headers::*;
+
+    use super::{CompressionMiddleware, Config};
+    use super::test_common::*;
+
+    fn post_from(agent: &str, chain: &iron::Chain) -> iron::Response {
+        post_data_with_raw_headers("/", &"a".repeat(1000), &[("Accept-Encoding", "gzip"), ("User-Agent", agent)], chain)
+    }
+
+    fn is_health_check(req: &iron::Request, _: &iron::Response) -> bool {
+        req.headers.get::<UserAgent>().map_or(false, |agent| agent.starts_with("HealthChecker"))
+    }
+
+    #[test]
+    fn it_should_skip_excluded_responses_with_a_named_reason() {
+        let middleware = CompressionMiddleware::with_config(Config::default().debug_header(true))
+            .exclude("health-check", is_health_check);
+        let stats = middleware.stats();
+        let chain = build_middleware_echo_chain(middleware);
+
+        let res = post_from("HealthChecker/1.0", &chain);
+        assert_eq!(res.headers.get::<ContentEncoding>(), None);
+        assert_eq!(res.headers.get_raw("X-Compression"), Some(&[b"skipped; reason=health-check".to_vec()][..]));
+        assert_eq!(stats.skipped_because("health-check"), 1);
+
+        let res = post_from("Mozilla/5.0", &chain);
+        assert_eq!(res.headers.get::<ContentEncoding>(), Some(&ContentEncoding(vec![Encoding::Gzip])));
+        assert_eq!(stats.compressed(), 1);
+    }
+
+    #[test]
+    fn it_should_only_compress_included_responses() {
+        let middleware = CompressionMiddleware::new()
+            .include("browsers", |req, _| req.headers.get::<UserAgent>().map_or(false, |agent| agent.starts_with("Mozilla")));
+        let stats = middleware.stats();
+        let chain = build_middleware_echo_chain(middleware);
+
+        assert_eq!(post_from("curl/7.0", &chain).headers.get::<ContentEncoding>(), None);
+        assert!(post_from("Mozilla/5.0", &chain).headers.has::<ContentEncoding>());
+        assert_eq!(stats.skipped_because("browsers"), 1);
+    }
+
+    #[test]
+    fn it_should_compose_predicates_with_built_in_rules() {
+        let middleware = CompressionMiddleware::new().exclude("health-check", is_health_check);
+        let stats = middleware.stats();
+        let chain = build_middleware_echo_chain(middleware);
+
+        let res = post_data_with_raw_accept_encoding("a", "gzip", &chain);
+        assert_eq!(res.headers.get::<ContentEncoding>(), None);
+        assert_eq!(stats.skipped_because("too-small"), 1);
+        assert_eq!(stats.skipped_because("health-check"), 0);
+    }
+}
+
+#[cfg(test)]
+mod path_pattern_tests {
+    use super::rules::{segments, Matcher};
+
+    fn matches(pattern: &str, path: &str) -> bool {
+        let path: Vec<&str> = segments(path).collect();
+        Matcher::path(pattern).matches(&path, None)
+    }
+
+    #[test]
+    fn it_should_match_literal_paths() {
+        assert!(matches("/health", "/health"));
+        assert!(!matches("/health", "/healthz"));
+        assert!(!matches("/health", "/health/deep"));
+    }
+
+    #[test]
+    fn it_should_match_a_single_star_within_a_segment() {
+        assert!(matches("/assets/*.js", "/assets/app.js"));
+        assert!(!matches("/assets/*.js", "/assets/app.css"));
+        assert!(!matches("/assets/*.js", "/assets/vendor/app.js"));
+        assert!(matches("/users/*/avatar", "/users/42/avatar"));
+    }
+
+    #[test]
+    fn it_should_match_any_number_of_segments_with_a_double_star() {
+        assert!(matches("/static/**", "/static"));
+        assert!(matches("/static/**", "/static/"));
+        assert!(matches("/static/**", "/static/css/site.css"));
+        assert!(!matches("/static/**", "/api/static"));
+        assert!(matches("/**/*.json", "/api/v1/users.json"));
+        assert!(matches("/**", "/"));
+    }
+}
+
+#[cfg(all(test, feature = "gzip", feature = "brotli"))]
+mod rule_tests {
+    extern crate iron_test;
+
+    use iron::prelude::*;
+    use iron::{BeforeMiddleware, Chain};
+    use self::iron_test::response;
+
+    use super::{CompressionEncoding, CompressionMiddleware, Config, RouteId, Rule, RuleLevel};
+    use super::codec::encode_with_level;
+    use super::test_common::*;
+
+    fn post_to(path: &str, accept_encoding: &str, chain: &Chain) -> Response {
+        post_data_with_raw_headers(path, &"a".repeat(1000), &[("Accept-Encoding", accept_encoding)], chain)
+    }
+
+    fn build_rule_chain() -> Chain {
+        build_middleware_echo_chain(CompressionMiddleware::with_config(Config::default().debug_header(true))
+            .rule("/api/stream/**", Rule::never())
+            .rule("/download/**", Rule::prefer(CompressionEncoding::Gzip).level(RuleLevel::Fastest))
+            .rule("/static/**", Rule::prefer(CompressionEncoding::Brotli).level(RuleLevel::Max)))
+    }
+
+    #[test]
+    fn it_should_never_compress_matching_paths() {
+        let res = post_to("/api/stream/events", "gzip", &build_rule_chain());
+        assert_eq!(content_encoding(&res), None);
+        assert_eq!(res.headers.get_raw("X-Compression"), Some(&[b"skipped; reason=rule".to_vec()][..]));
+    }
+
+    #[test]
+    fn it_should_prefer_the_encoding_and_level_of_matching_rules() {
+        let chain = build_rule_chain();
+
+        let res = post_to("/download/dump.sql", "br, gzip", &chain);
+        assert_eq!(content_encoding(&res), Some(String::from("gzip")));
+
+        let res = post_to("/static/app.js", "gzip, br", &chain);
+        assert_eq!(content_encoding(&res), Some(String::from("br")));
+        let compressed_bytes = response::extract_body_to_bytes(res);
+        assert_eq!(compressed_bytes, encode_with_level(CompressionEncoding::Brotli, 11, "a".repeat(1000).as_bytes()).unwrap());
+    }
+
+    #[test]
+    fn it_should_use_the_configured_priority_for_other_paths() {
+        let res = post_to("/index.html", "gzip, br", &build_rule_chain());
+        assert_eq!(content_encoding(&res), Some(String::from("br")));
+    }
+
+    #[test]
+    fn it_should_fall_back_to_encodings_the_client_accepts() {
+        let res = post_to("/static/app.js", "gzip", &build_rule_chain());
+        assert_eq!(content_encoding(&res), Some(String::from("gzip")));
+    }
+
+    struct SetRouteId(&'static str);
+
+    impl BeforeMiddleware for SetRouteId {
+        fn before(&self, req: &mut Request) -> IronResult<()> {
+            req.extensions.insert::<RouteId>(String::from(self.0));
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn it_should_match_route_ids() {
+        let mut chain = build_middleware_echo_chain(CompressionMiddleware::new().route_rule("events", Rule::never()));
+        chain.link_before(SetRouteId("events"));
+        assert_eq!(content_encoding(&post_to("/anywhere", "gzip", &chain)), None);
+
+        let mut chain = build_middleware_echo_chain(CompressionMiddleware::new().route_rule("events", Rule::never()));
+        chain.link_before(SetRouteId("index"));
+        assert_eq!(content_encoding(&post_to("/anywhere", "gzip", &chain)), Some(String::from("gzip")));
+    }
+}
+
+#[cfg(all(test, feature = "gzip"))]
+mod error_response_tests {
+    extern crate iron_test;
+
+    use std::error::Error;
+    use std::fmt;
+    use std::io::Read;
+    use iron::prelude::*;
+    use iron::headers::*;
+    use iron::{Chain, status};
+    use self::iron_test::{request, response};
+    use libflate::gzip;
+
+    use super::{CompressionMiddleware, Config};
+
+    #[derive(Debug)]
+    struct EchoError;
+
+    impl fmt::Display for EchoError {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            f.write_str("echo error")
+        }
+    }
+
+    impl Error for EchoError {}
+
+    fn build_failing_echo_chain(config: Config) -> Chain {
+        let mut chain = Chain::new(|req: &mut Request| {
+            let mut body: Vec<u8> = vec!();
+            req.body.read_to_end(&mut body).unwrap();
+            Err(IronError::new(EchoError, (status::BadRequest, body)))
+        });
+        chain.link_after(CompressionMiddleware::with_config(config));
+        chain
+    }
+
+    fn post_to_failing_chain(data: &str, chain: &Chain) -> IronError {
+        let mut headers = Headers::new();
+        headers.set_raw("Accept-Encoding", vec![b"gzip".to_vec()]);
+        request::post("http://localhost:3000/", headers, data, chain).err().unwrap()
+    }
+
+    #[test]
+    fn it_should_compress_error_responses_and_keep_the_error() {
+        let value = "{\"error\": \"bad request\"}".repeat(100);
+        let err = post_to_failing_chain(&value, &build_failing_echo_chain(Config::default()));
+
+        assert_eq!(err.error.to_string(), "echo error");
+        assert_eq!(err.response.status, Some(status::BadRequest));
+        assert_eq!(err.response.headers.get::<ContentEncoding>(), Some(&ContentEncoding(vec![Encoding::Gzip])));
+
+        let compressed_bytes = response::extract_body_to_bytes(err.response);
+        let mut decoder = gzip::Decoder::new(&compressed_bytes[..]).unwrap();
+        let mut decoded_data = Vec::new();
+        decoder.read_to_end(&mut decoded_data).unwrap();
+        assert_eq!(decoded_data, value.into_bytes());
+    }
+
+    #[test]
+    fn it_should_negotiate_error_responses_like_other_responses() {
+        let err = post_to_failing_chain("small", &build_failing_echo_chain(Config::default().debug_header(true)));
+
+        assert_eq!(err.response.headers.get::<ContentEncoding>(), None);
+        assert_eq!(err.response.headers.get_raw("X-Compression"), Some(&[b"skipped; reason=too-small".to_vec()][..]));
+        assert_eq!(response::extract_body_to_string(err.response), "small");
+    }
+}
+
+#[cfg(all(test, feature = "parallel"))]
+mod parallel_gzip_tests {
+    extern crate iron_test;
+    extern crate rand;
+
+    use std::io::Write;
+    use std::sync::Arc;
+    use iron::headers::*;
+    use self::iron_test::{response};
+    use self::rand::Rng;
+    use self::rand::distributions::Alphanumeric;
+
+    use super::{Config, ParallelGzip};
+    use super::codec::GzipHeader;
+    use super::parallel::ParallelGzipEncoder;
+    use super::pool::ThreadPool;
+    use super::test_common::*;
+
+    fn build_parallel_echo_chain() -> iron::Chain {
+        build_configured_echo_chain(Config::default().parallel_gzip(ParallelGzip {
+            min_bytes: 10_000,
+            threads: 3,
+            block_size: 1000,
+        }))
+    }
+
+    fn compress(data: &[u8], block_size: usize) -> Vec<u8> {
+        let pool = Arc::new(ThreadPool::new(2));
+        let config = ParallelGzip { block_size, ..ParallelGzip::default() };
+        let mut encoder = ParallelGzipEncoder::new(Vec::new(), pool, &config, 6, &GzipHeader::default()).unwrap();
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    #[test]
+    fn it_should_compress_empty_input() {
+        assert_eq!(gunzip(&compress(b"", 16)), b"");
+    }
+
+    #[test]
+    fn it_should_compress_input_smaller_than_a_block() {
+        assert_eq!(gunzip(&compress(b"Batman!", 16)), b"Batman!");
+    }
+
+    #[test]
+    fn it_should_join_many_blocks_in_order() {
+        let data: Vec<u8> = (0..100_000u32).map(|i| (i % 251) as u8).collect();
+        assert_eq!(gunzip(&compress(&data, 1000)), data);
+    }
+
+    #[test]
+    fn it_should_compress_large_responses_in_parallel_blocks() {
+        let value: String = rand::thread_rng().sample_iter(&Alphanumeric).take(25_500).map(char::from).collect();
+        let chain = build_parallel_echo_chain();
+        let res = post_data_with_raw_accept_encoding(&value, "gzip", &chain);
+
+        assert_eq!(res.headers.get::<ContentEncoding>(), Some(&ContentEncoding(vec![Encoding::Gzip])));
+        let compressed_bytes = response::extract_body_to_bytes(res);
+        assert_eq!(gunzip(&compressed_bytes), value.into_bytes());
+    }
+
+    #[test]
+    fn it_should_decode_to_the_same_data_as_the_streaming_encoder() {
+        let value = "Na".repeat(20_000);
+        let parallel = post_data_with_raw_accept_encoding(&value, "gzip", &build_parallel_echo_chain());
+        let streaming = post_data_with_raw_accept_encoding(&value, "gzip", &build_compressed_echo_chain(false));
+
+        let parallel_bytes = response::extract_body_to_bytes(parallel);
+        let streaming_bytes = response::extract_body_to_bytes(streaming);
+        assert_eq!(gunzip(&parallel_bytes), gunzip(&streaming_bytes));
+    }
+
+    #[test]
+    fn it_should_stream_with_the_debug_header() {
+        let value = "a".repeat(12_345);
+        let chain = build_configured_echo_chain(Config::default().debug_header(true).parallel_gzip(ParallelGzip {
+            min_bytes: 10_000,
+            threads: 2,
+            block_size: 1000,
+        }));
+        let res = post_data_with_raw_accept_encoding(&value, "gzip", &chain);
+
+        assert_eq!(res.headers.get_raw("X-Compression").unwrap()[0], b"gzip; in=12345".to_vec());
+        assert_eq!(res.headers.get::<ContentLength>(), None);
+        let compressed_bytes = response::extract_body_to_bytes(res);
+        assert_eq!(gunzip(&compressed_bytes), value.into_bytes());
+    }
+
+    #[test]
+    #[cfg(feature = "brotli")]
+    fn it_should_not_use_parallel_compression_for_other_encodings() {
+        let value = "a".repeat(20_000);
+        let chain = build_parallel_echo_chain();
+        let res = post_data_with_raw_accept_encoding(&value, "br", &chain);
+
+        assert_eq!(res.headers.get::<ContentEncoding>(), Some(&ContentEncoding(vec![Encoding::EncodingExt(String::from("br"))])));
+    }
+}
+
+#[cfg(all(feature = "gzip", feature = "brotli", test))]
+mod debug_header_tests {
+    extern crate iron_test;
+
+    use std::io::Read;
+    use iron::prelude::*;
+    use iron::headers::*;
+    use self::iron_test::{request, response};
+    use brotli;
+
+    use super::Config;
+    use super::test_common::*;
+
+    fn debug_header(res: &Response) -> Option<String> {
+        res.headers.get_raw("X-Compression").map(|values| String::from_utf8(values[0].clone()).unwrap())
+    }
+
+    #[test]
+    fn it_should_not_add_debug_header_by_default() {
+        let value = "a".repeat(1000);
+        let chain = build_compressed_echo_chain(false);
+        let res = post_data_with_raw_accept_encoding(&value, "br", &chain);
+
+        assert_eq!(debug_header(&res), None);
+    }
+
+    #[test]
+    fn it_should_describe_compressed_responses_and_keep_the_body_intact() {
+        let value = "a".repeat(1000);
+        let chain = build_configured_echo_chain(Config::default().debug_header(true));
+        let res = post_data_with_raw_accept_encoding(&value, "br", &chain);
+
+        assert_eq!(debug_header(&res), Some(String::from("br; in=1000")));
+
+        let compressed_bytes = response::extract_body_to_bytes(res);
+        let mut decoder = brotli::Decompressor::new(&compressed_bytes[..], 4096);
+        let mut decoded_data = Vec::new();
+        decoder.read_to_end(&mut decoded_data).unwrap();
+        assert_eq!(decoded_data, value.into_bytes());
+    }
+
+    #[test]
+    fn it_should_not_change_the_framing_of_compressed_responses() {
+        let value = "a".repeat(1000);
+        let plain = build_compressed_echo_chain(false);
+        let debugged = build_configured_echo_chain(Config::default().debug_header(true));
+
+        let plain_res = post_data_with_raw_accept_encoding(&value, "br", &plain);
+        let debugged_res = post_data_with_raw_accept_encoding(&value, "br", &debugged);
+        assert_eq!(debugged_res.headers.get::<ContentLength>(), plain_res.headers.get::<ContentLength>());
+        assert_eq!(response::extract_body_to_bytes(debugged_res), response::extract_body_to_bytes(plain_res));
+    }
+
+    #[test]
+    fn it_should_not_report_the_compressed_size_of_streamed_responses() {
+        let value = "a".repeat(1000);
+        let chain = build_configured_echo_chain(Config::default().debug_header(true));
+        let res = post_data_with_raw_accept_encoding(&value, "gzip", &chain);
+
+        assert_eq!(debug_header(&res), Some(String::from("gzip; in=1000")));
+    }
+
+    #[cfg(feature = "digest")]
+    #[test]
+    fn it_should_report_the_compressed_size_of_buffered_responses() {
+        let value = "a".repeat(1000);
+        let chain = build_configured_echo_chain(Config::default()
+            .debug_header(true)
+            .digests(vec![super::DigestAlgorithm::Sha256]));
+        let res = post_data_with_raw_accept_encoding(&value, "gzip", &chain);
+        let header = debug_header(&res);
+
+        let compressed_bytes = response::extract_body_to_bytes(res);
+        assert_eq!(header, Some(format!("gzip; in=1000; out={}", compressed_bytes.len())));
+    }
+
+    #[test]
+    fn it_should_describe_skipped_responses() {
+        let chain = build_configured_echo_chain(Config::default().debug_header(true));
+        let res = post_data_with_raw_accept_encoding("small", "gzip", &chain);
+
+        assert_eq!(debug_header(&res), Some(String::from("skipped; reason=too-small")));
+    }
+
+    #[test]
+    fn it_should_only_add_debug_header_for_requests_with_the_debug_token() {
+        let value = "a".repeat(1000);
+        let chain = build_configured_echo_chain(Config::default().debug_token("X-Debug-Token", "secret"));
+
+        let res = post_data_with_raw_accept_encoding(&value, "gzip", &chain);
+        assert_eq!(debug_header(&res), None);
+
+        let mut headers = Headers::new();
+        headers.set_raw("Accept-Encoding", vec![b"gzip".to_vec()]);
+        headers.set_raw("X-Debug-Token", vec![b"wrong".to_vec()]);
+        let res = request::post("http://localhost:3000/", headers, &value, &chain).unwrap();
+        assert_eq!(debug_header(&res), None);
+
+        let mut headers = Headers::new();
+        headers.set_raw("Accept-Encoding", vec![b"gzip".to_vec()]);
+        headers.set_raw("X-Debug-Token", vec![b"secret".to_vec()]);
+        let res = request::post("http://localhost:3000/", headers, &value, &chain).unwrap();
+        assert!(debug_header(&res).unwrap().starts_with("gzip; in=1000"));
+    }
+}
+
+#[cfg(all(feature = "hyper", feature = "gzip", test))]
+mod hyper_adapter_tests {
+    use std::io::{self, Read, Write, Cursor};
+    use std::net::SocketAddr;
+    use std::time::Duration;
+    use hyper::buffer::BufReader;
+    use hyper::header::Headers;
+    use hyper::net::NetworkStream;
+    use hyper::server::{Request, Response};
+
+    use super::{BreachMitigation, Compressor, Config, Rule, Sensitive};
+    #[cfg(feature = "deflate")]
+    use super::CompressionEncoding;
+    #[cfg(feature = "deflate")]
+    use super::codec::encode;
+    use super::hyper_adapter;
+    use super::test_common::gunzip;
+
+    struct MockStream(Cursor<Vec<u8>>);
+
+    impl Read for MockStream {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> { self.0.read(buf) }
+    }
+
+    impl Write for MockStream {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> { Ok(buf.len()) }
+        fn flush(&mut self) -> io::Result<()> { Ok(()) }
+    }
+
+    impl NetworkStream for MockStream {
+        fn peer_addr(&mut self) -> io::Result<SocketAddr> { Ok("127.0.0.1:3000".parse().unwrap()) }
+        fn set_read_timeout(&self, _: Option<Duration>) -> io::Result<()> { Ok(()) }
+        fn set_write_timeout(&self, _: Option<Duration>) -> io::Result<()> { Ok(()) }
+    }
+
+    fn respond<F>(accept_encoding: &str, respond: F) -> (String, Vec<u8>)
+        where F: FnOnce(&Request, Response) -> io::Result<()> {
+        respond_to("/", &format!("Accept-Encoding: {}\r\n", accept_encoding), respond)
+    }
+
+    fn respond_to<F>(path: &str, raw_headers: &str, respond: F) -> (String, Vec<u8>)
+        where F: FnOnce(&Request, Response) -> io::Result<()> {
+        let raw_request = format!("GET {} HTTP/1.1\r\nHost: localhost\r\n{}\r\n", path, raw_headers);
+        let mut stream = MockStream(Cursor::new(raw_request.into_bytes()));
+        let mut reader = BufReader::new(&mut stream as &mut NetworkStream);
+        let req = Request::new(&mut reader, "127.0.0.1:3000".parse().unwrap()).unwrap();
+
+        let mut output: Vec<u8> = vec!();
+        {
+            let mut headers = Headers::new();
+            let res = Response::new(&mut output, &mut headers);
+            respond(&req, res).unwrap();
+        }
+
+        let separator = output.windows(4).position(|window| window == b"\r\n\r\n").unwrap();
+        let head = String::from_utf8(output[..separator].to_vec()).unwrap();
+        (head, output[separator + 4..].to_vec())
+    }
+
+    fn dechunk(body: &[u8]) -> Vec<u8> {
+        let mut dechunked: Vec<u8> = vec!();
+        let mut rest = body;
+        loop {
+            let line_end = rest.windows(2).position(|window| window == b"\r\n").unwrap();
+            let size = usize::from_str_radix(::std::str::from_utf8(&rest[..line_end]).unwrap(), 16).unwrap();
+            if size == 0 {
+                return dechunked;
+            }
+            dechunked.extend_from_slice(&rest[line_end + 2..line_end + 2 + size]);
+            rest = &rest[line_end + 2 + size + 2..];
+        }
+    }
+
+    #[test]
+    fn it_should_send_compressed_body() {
+        let value = "a".repeat(1000);
+        let (head, body) = respond("gzip", |req, res| hyper_adapter::send(req, res, value.as_bytes(), &Compressor::new()));
+
+        assert!(head.contains("Content-Encoding: gzip"));
+        assert!(head.contains(&format!("Content-Length: {}", body.len())));
+        assert_eq!(gunzip(&body), value.into_bytes());
+    }
+
+    #[test]
+    fn it_should_send_small_body_uncompressed() {
+        let (head, body) = respond("gzip", |req, res| hyper_adapter::send(req, res, b"small", &Compressor::new()));
+
+        assert!(!head.contains("Content-Encoding"));
+        assert_eq!(body, b"small".to_vec());
+    }
+
+    #[test]
+    fn it_should_stream_compressed_body() {
+        let value = "a".repeat(1000);
+        let (head, body) = respond("gzip", |req, mut res| {
+            res.headers_mut().set(::iron::headers::ContentLength(1000));
+            let mut writer = hyper_adapter::start(req, res, &Compressor::new())?;
+            writer.write_all(value.as_bytes())?;
+            writer.end()
+        });
+
+        assert!(head.contains("Content-Encoding: gzip"));
+        assert!(head.contains("Transfer-Encoding: chunked"));
+        assert_eq!(gunzip(&dechunk(&body)), value.into_bytes());
+    }
+
+    #[test]
+    fn it_should_report_the_compressed_size_of_sent_responses_only() {
+        let value = "a".repeat(1000);
+        let compressor = Compressor::with_config(Config::default().debug_header(true));
+        let (head, body) = respond("gzip", |req, res| hyper_adapter::send(req, res, value.as_bytes(), &compressor));
+        assert!(head.lines().any(|line| line == format!("X-Compression: gzip; in=1000; out={}", body.len())));
+
+        let (head, _) = respond("gzip", |req, mut res| {
+            res.headers_mut().set(::iron::headers::ContentLength(1000));
+            let mut writer = hyper_adapter::start(req, res, &compressor)?;
+            writer.write_all(value.as_bytes())?;
+            writer.end()
+        });
+        assert!(head.lines().any(|line| line == "X-Compression: gzip; in=1000"));
+    }
+
+    #[test]
+    #[cfg(feature = "parallel")]
+    fn it_should_compress_large_bodies_in_parallel() {
+        use std::sync::Arc;
+        use super::{CompressionEncoding, ParallelGzip};
+        use super::codec::{default_level, GzipHeader};
+        use super::parallel::ParallelGzipEncoder;
+        use super::pool::ThreadPool;
+
+        let value = "Na".repeat(20_000);
+        let parallel = ParallelGzip { min_bytes: 10_000, threads: 2, block_size: 1000 };
+        let compressor = Compressor::with_config(Config::default().parallel_gzip(parallel.clone()));
+        let (head, body) = respond("gzip", |req, res| hyper_adapter::send(req, res, value.as_bytes(), &compressor));
+
+        let level = default_level(CompressionEncoding::Gzip);
+        let mut encoder = ParallelGzipEncoder::new(Vec::new(), Arc::new(ThreadPool::new(2)), &parallel, level, &GzipHeader::default()).unwrap();
+        encoder.write_all(value.as_bytes()).unwrap();
+        assert!(head.contains("Content-Encoding: gzip"));
+        assert_eq!(body, encoder.finish().unwrap());
+    }
+
+    #[test]
+    fn it_should_apply_path_rules() {
+        let value = "a".repeat(1000);
+        let compressor = Compressor::new().rule("/stream/**", Rule::never());
+        let (head, body) = respond_to("/stream/events?since=1", "Accept-Encoding: gzip\r\n", |req, res| hyper_adapter::send(req, res, value.as_bytes(), &compressor));
+
+        assert!(!head.contains("Content-Encoding"));
+        assert_eq!(body, value.into_bytes());
+        assert_eq!(compressor.stats().skipped_because("rule"), 1);
+    }
+
+    #[test]
+    fn it_should_skip_sensitive_responses_and_remove_the_marker() {
+        let value = "a".repeat(1000);
+        let compressor = Compressor::with_config(Config::default().breach_mitigation(BreachMitigation::default().skip_sensitive(true)));
+        let (head, _) = respond("gzip", |req, mut res| {
+            res.headers_mut().set(Sensitive);
+            hyper_adapter::send(req, res, value.as_bytes(), &compressor)
+        });
+
+        assert!(!head.contains("Content-Encoding"));
+        assert!(!head.contains("X-Iron-Pack-Sensitive"));
+        assert_eq!(compressor.stats().skipped_because("sensitive"), 1);
+    }
+
+    #[test]
+    fn it_should_send_transfer_coded_body() {
+        let value = "a".repeat(1000);
+        let compressor = Compressor::with_config(Config::default().transfer_coding(true));
+        let (head, body) = respond_to("/", "TE: gzip\r\n", |req, res| hyper_adapter::send(req, res, value.as_bytes(), &compressor));
+
+        assert!(head.contains("Transfer-Encoding: gzip, chunked"));
+        assert!(!head.contains("Content-Encoding"));
+        assert_eq!(gunzip(&dechunk(&body)), value.into_bytes());
+    }
+
+    #[test]
+    #[cfg(feature = "deflate")]
+    fn it_should_transcode_encoded_body() {
+        let value = "a".repeat(1000);
+        let encoded = encode(CompressionEncoding::Deflate, value.as_bytes()).unwrap();
+        let compressor = Compressor::with_config(Config::default().transcode(true).min_bytes(0));
+        let (head, body) = respond("gzip", |req, mut res| {
+            res.headers_mut().set(::iron::headers::ContentEncoding(vec![::iron::headers::Encoding::Deflate]));
+            hyper_adapter::send(req, res, &encoded, &compressor)
+        });
+
+        assert!(head.contains("Content-Encoding: gzip"));
+        assert_eq!(gunzip(&body), value.into_bytes());
+    }
+}
+
+#[cfg(all(feature = "unstable", feature = "gzip", feature = "deflate", feature = "brotli", test))]
+mod middleware_benchmarks {
+    macro_rules! bench_chain_with_header_and_size {
+        ($name:ident, $chain:expr, $header:expr, $response_size:expr) => {
+            #[bench]
+            fn $name(b: &mut Bencher) {
+                let chain = $chain;
+                let mut rng = StdRng::seed_from_u64(0);
+
+                b.iter(|| {
+                    let data: String = (&mut rng).sample_iter(&Alphanumeric).take($response_size).map(char::from).collect();
+                    let res = post_data_with_accept_encoding(&data,
+                                                           $header,
+                                                           &chain);
+                    let compressed_bytes = response::extract_body_to_bytes(res);
+
+                    assert!(compressed_bytes.len() > 0);
+                })
+            }
+        };
+    }
+
+    macro_rules! bench_chains_with_size {
+        ($mod_name:ident, $size:expr) => {
+            mod $mod_name {
+                extern crate iron_test;
+                extern crate test;
+                extern crate rand;
+
+                use std::io::Read;
+                use iron::prelude::*;
+                use iron::{Chain, status};
+                use iron::headers::*;
+                use self::test::Bencher;
+                use self::iron_test::{response};
+                use self::rand::{Rng, SeedableRng};
+                use self::rand::distributions::Alphanumeric;
+                use self::rand::rngs::StdRng;
+                #[cfg(feature = "libdeflate")]
+                use super::super::Config;
+                use super::super::test_common::*;
+
+                fn build_echo_chain() -> Chain {
+                    let chain = Chain::new(|req: &mut Request| {
+                        let mut body: Vec<u8> = vec!();
+                        req.body.read_to_end(&mut body).unwrap();
+                        Ok(Response::with((status::Ok, body)))
+                    });
+                    return chain;
+                }
+
+                bench_chain_with_header_and_size!(without_middleware, build_echo_chain(), None, $size);
+                bench_chain_with_header_and_size!(with_middleware_no_accept_header, build_compressed_echo_chain(false), None, $size);
+                bench_chain_with_header_and_size!(with_middleware_gzip,
+                                                  build_compressed_echo_chain(false),
+                                                  Some(AcceptEncoding(vec![qitem(Encoding::Gzip)])),
+                                                  $size);
+                bench_chain_with_header_and_size!(with_middleware_deflate,
+                                                  build_compressed_echo_chain(false),
+                                                  Some(AcceptEncoding(vec![qitem(Encoding::Deflate)])),
+                                                  $size);
+                bench_chain_with_header_and_size!(with_middleware_brotli,
+                                                  build_compressed_echo_chain(false),
+                                                  Some(AcceptEncoding(vec![qitem(Encoding::EncodingExt(String::from("br")))])),
+                                                  $size);
+                #[cfg(feature = "libdeflate")]
+                bench_chain_with_header_and_size!(with_middleware_gzip_streaming,
+                                                  build_configured_echo_chain(Config::default().one_shot_limit(0)),
+                                                  Some(AcceptEncoding(vec![qitem(Encoding::Gzip)])),
+                                                  $size);
+                #[cfg(feature = "libdeflate")]
+                bench_chain_with_header_and_size!(with_middleware_gzip_one_shot,
+                                                  build_configured_echo_chain(Config::default().one_shot_limit(u64::max_value())),
+                                                  Some(AcceptEncoding(vec![qitem(Encoding::Gzip)])),
+                                                  $size);
+                #[cfg(feature = "libdeflate")]
+                bench_chain_with_header_and_size!(with_middleware_deflate_streaming,
+                                                  build_configured_echo_chain(Config::default().one_shot_limit(0)),
+                                                  Some(AcceptEncoding(vec![qitem(Encoding::Deflate)])),
+                                                  $size);
+                #[cfg(feature = "libdeflate")]
+                bench_chain_with_header_and_size!(with_middleware_deflate_one_shot,
+                                                  build_configured_echo_chain(Config::default().one_shot_limit(u64::max_value())),
+                                                  Some(AcceptEncoding(vec![qitem(Encoding::Deflate)])),
+                                                  $size);
+            }
+        };
+    }
+
+    bench_chains_with_size!(response_1kb, 1024);
+    bench_chains_with_size!(response_128kb, 128 * 1024);
+    bench_chains_with_size!(response_1mb, 1024 * 1024);
+}
+
+#[cfg(all(feature = "unstable", feature = "libdeflate", feature = "gzip", test))]
+mod one_shot_benchmarks {
+    extern crate test;
+    extern crate rand;
+
+    use std::io::Write;
+    use self::test::Bencher;
+    use self::rand::{Rng, SeedableRng};
+    use self::rand::distributions::Alphanumeric;
+    use self::rand::rngs::StdRng;
+
+    use super::CompressionEncoding;
+    use super::codec::{encode, Encoder};
+
+    fn data(size: usize) -> Vec<u8> {
+        StdRng::seed_from_u64(0).sample_iter(&Alphanumeric).take(size).collect()
+    }
+
+    fn bench_one_shot(b: &mut Bencher, size: usize) {
+        let data = data(size);
+        b.bytes = size as u64;
+        b.iter(|| encode(CompressionEncoding::Gzip, &data).unwrap());
+    }
+
+    fn bench_streaming(b: &mut Bencher, size: usize) {
+        let data = data(size);
+        b.bytes = size as u64;
+        b.iter(|| {
+            let mut encoder = Encoder::new(CompressionEncoding::Gzip, Vec::new()).unwrap();
+            encoder.write_all(&data).unwrap();
+            encoder.finish().unwrap()
+        });
+    }
+
+    #[bench]
+    fn gzip_one_shot_1kb(b: &mut Bencher) { bench_one_shot(b, 1024) }
+
+    #[bench]
+    fn gzip_streaming_1kb(b: &mut Bencher) { bench_streaming(b, 1024) }
+
+    #[bench]
+    fn gzip_one_shot_128kb(b: &mut Bencher) { bench_one_shot(b, 128 * 1024) }
+
+    #[bench]
+    fn gzip_streaming_128kb(b: &mut Bencher) { bench_streaming(b, 128 * 1024) }
+
+    #[bench]
+    fn gzip_one_shot_1mb(b: &mut Bencher) { bench_one_shot(b, 1024 * 1024) }
+
+    #[bench]
+    fn gzip_streaming_1mb(b: &mut Bencher) { bench_streaming(b, 1024 * 1024) }
+}
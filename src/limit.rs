@@ -0,0 +1,70 @@
+//! A global limit on the number of responses that are compressed at the same time.
+
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+/// What happens to a response that should be compressed while the concurrency limit is reached
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum SaturationPolicy {
+    /// Send the response uncompressed
+    Identity,
+    /// Wait up to the given time until another response is compressed, and send the response
+    /// uncompressed if no slot frees up in time. The thread handling the request is blocked
+    /// while waiting.
+    Queue(Duration),
+}
+
+/// A counting semaphore for compressions in progress
+pub struct ConcurrencyLimit {
+    max: usize,
+    active: Mutex<usize>,
+    released: Condvar,
+}
+
+/// A slot of the concurrency limit, released when dropped
+pub struct Permit(Arc<ConcurrencyLimit>);
+
+impl ConcurrencyLimit {
+    pub fn new(max: usize) -> Arc<ConcurrencyLimit> {
+        Arc::new(ConcurrencyLimit {
+            max: max.max(1),
+            active: Mutex::new(0),
+            released: Condvar::new(),
+        })
+    }
+
+    fn lock(&self) -> MutexGuard<'_, usize> {
+        // The counter stays consistent even if a thread panicked while holding the lock
+        self.active.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Takes a slot if one is free
+    pub fn try_acquire(limit: &Arc<ConcurrencyLimit>) -> Option<Permit> {
+        let mut active = limit.lock();
+        if *active < limit.max {
+            *active += 1;
+            Some(Permit(limit.clone()))
+        } else {
+            None
+        }
+    }
+
+    /// Waits up to `timeout` for a free slot and takes it
+    pub fn acquire_timeout(limit: &Arc<ConcurrencyLimit>, timeout: Duration) -> Option<Permit> {
+        let deadline = Instant::now() + timeout;
+        let mut active = limit.lock();
+        while *active >= limit.max {
+            let remaining = deadline.checked_duration_since(Instant::now())?;
+            active = limit.released.wait_timeout(active, remaining).unwrap_or_else(|poisoned| poisoned.into_inner()).0;
+        }
+        *active += 1;
+        Some(Permit(limit.clone()))
+    }
+}
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        *self.0.lock() -= 1;
+        self.0.released.notify_one();
+    }
+}
@@ -0,0 +1,403 @@
+//! The iron adapter: an `AfterMiddleware` and `WriteBody` wrappers around the encoders.
+
+use std::io;
+use std::io::Write;
+use std::mem;
+use std::time::{Duration, Instant};
+use iron::prelude::*;
+use iron::headers::*;
+use iron::modifier::Modifier;
+use iron::typemap::Key;
+use iron::{AfterMiddleware, status};
+use iron::response::WriteBody;
+
+use breach::{self, Sensitive};
+use codec::Decoder;
+use compressor::{BodyEncoder, BodyEncoding, Coding, Compressor, Facts, Plan};
+use config::Config;
+use limit::Permit;
+use negotiation::{negotiate_exchange, negotiate_transfer_coding, CompressionEncoding, Decision, Exchange};
+use rules::{Matcher, Rule, RouteId};
+use stats::{InFlight, Stats};
+
+impl Key for Sensitive {
+    type Value = Sensitive;
+}
+
+impl Modifier<Response> for Sensitive {
+    fn modify(self, res: &mut Response) {
+        res.extensions.insert::<Sensitive>(self);
+    }
+}
+
+impl Key for RouteId {
+    type Value = String;
+}
+
+/// Decompresses the wrapped, encoded body while it is written. The encoded body is read into
+/// memory first, since the decoders read their input.
+struct DecodedBody(CompressionEncoding, Box<WriteBody>);
+
+impl WriteBody for DecodedBody {
+    fn write_body(&mut self, w: &mut Write) -> io::Result<()> {
+        let encoded = buffer(&mut *self.1, 0)?;
+        let mut decoder = Decoder::new(self.0, &encoded[..])?;
+        io::copy(&mut decoder, w).map(|_| ())
+    }
+}
+
+/// Compresses the wrapped body while it is written. Bodies that write their whole length in a
+/// single call, as `Vec<u8>` and `String` bodies do, are compressed in one go if the
+/// `BodyEncoding` allows it. Other bodies, like files, which are copied in chunks, are streamed.
+struct EncodedBody(BodyEncoding, Box<WriteBody>);
+
+impl WriteBody for EncodedBody {
+    fn write_body(&mut self, w: &mut Write) -> io::Result<()> {
+        let mut writer = EncodingWriter { body_encoding: &self.0, state: EncodingState::Pending(w) };
+        self.1.write_body(&mut writer)?;
+        writer.finish()
+    }
+}
+
+enum EncodingState<'a> {
+    /// Nothing is written yet
+    Pending(&'a mut Write),
+    /// The whole body is compressed in one go
+    Written,
+    /// The body is written in chunks
+    Streaming(BodyEncoder<&'a mut Write>),
+}
+
+struct EncodingWriter<'a, 'b> {
+    body_encoding: &'b BodyEncoding,
+    state: EncodingState<'a>,
+}
+
+impl<'a, 'b> EncodingWriter<'a, 'b> {
+    fn finish(self) -> io::Result<()> {
+        match self.state {
+            EncodingState::Pending(w) => w.write_all(&self.body_encoding.encode(&[])?),
+            EncodingState::Written => Ok(()),
+            EncodingState::Streaming(encoder) => encoder.finish().map(|_| ()),
+        }
+    }
+}
+
+impl<'a, 'b> Write for EncodingWriter<'a, 'b> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        match mem::replace(&mut self.state, EncodingState::Written) {
+            EncodingState::Pending(w) => {
+                if self.body_encoding.one_shot_length == Some(buf.len() as u64) {
+                    w.write_all(&self.body_encoding.encode(buf)?)?;
+                } else {
+                    let mut encoder = self.body_encoding.encoder(w)?;
+                    encoder.write_all(buf)?;
+                    self.state = EncodingState::Streaming(encoder);
+                }
+                Ok(buf.len())
+            }
+            EncodingState::Written => Err(io::Error::new(io::ErrorKind::InvalidData, "the body is longer than its Content-Length")),
+            EncodingState::Streaming(mut encoder) => {
+                let written = encoder.write(buf);
+                self.state = EncodingState::Streaming(encoder);
+                written
+            }
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self.state {
+            EncodingState::Pending(ref mut w) => w.flush(),
+            EncodingState::Written => Ok(()),
+            EncodingState::Streaming(ref mut encoder) => encoder.flush(),
+        }
+    }
+}
+
+/// Accumulates the time spent in the wrapped writer
+struct TimedWriter<'a> {
+    inner: &'a mut Write,
+    spent: Duration,
+}
+
+impl<'a> Write for TimedWriter<'a> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let start = Instant::now();
+        let written = self.inner.write(buf);
+        self.spent += start.elapsed();
+        written
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        let start = Instant::now();
+        let flushed = self.inner.flush();
+        self.spent += start.elapsed();
+        flushed
+    }
+}
+
+/// Counts a streamed compression as in flight, and holds its slot of the concurrency limit,
+/// until the wrapped body is written. Measures the encoder throughput on the way.
+struct TrackedBody {
+    _permit: Option<Permit>,
+    _in_flight: InFlight,
+    stats: Stats,
+    content_length: Option<u64>,
+    body: Box<WriteBody>,
+}
+
+impl WriteBody for TrackedBody {
+    fn write_body(&mut self, w: &mut Write) -> io::Result<()> {
+        let start = Instant::now();
+        let mut timed = TimedWriter { inner: w, spent: Duration::from_secs(0) };
+        self.body.write_body(&mut timed)?;
+
+        if let Some(length) = self.content_length {
+            let encoding_time = start.elapsed().checked_sub(timed.spent).unwrap_or_default();
+            self.stats.record_throughput(length, encoding_time);
+        }
+        Ok(())
+    }
+}
+
+/// A named rule deciding whether a response may be compressed
+struct Predicate {
+    name: &'static str,
+    include: bool,
+    test: Box<Fn(&Request, &Response) -> bool + Send + Sync>,
+}
+
+/// Writes a body into memory
+fn buffer(body: &mut WriteBody, capacity: u64) -> io::Result<Vec<u8>> {
+    let mut buffered: Vec<u8> = Vec::with_capacity(capacity as usize);
+    body.write_body(&mut buffered)?;
+    Ok(buffered)
+}
+
+/// Decides whether an iron response should be compressed, and using which encoding.
+///
+/// This is the same decision `CompressionMiddleware` makes, so handlers can use it to pick
+/// between precompressed variants, or logging middleware can report why a response was not
+/// compressed.
+pub fn negotiate(req: &Request, res: &Response, config: &Config) -> Decision {
+    negotiate_exchange(&exchange(req, res), config)
+}
+
+/// Decides whether an iron response should be compressed as transfer-coding, based on the `TE`
+/// request header
+pub fn negotiate_transfer(req: &Request, res: &Response, config: &Config) -> Decision {
+    negotiate_transfer_coding(req.headers.get_raw("TE"), &exchange(req, res), config)
+}
+
+fn exchange<'a>(req: &'a Request, res: &Response) -> Exchange<'a> {
+    Exchange {
+        accept_encoding: req.headers.get_raw("Accept-Encoding"),
+        has_body: res.body.is_some(),
+        content_encoded: res.headers.has::<ContentEncoding>(),
+        content_length: res.headers.get::<ContentLength>().map(|&ContentLength(length)| length),
+        cross_site: breach::is_cross_site(&req.headers),
+        sensitive: is_sensitive(res),
+    }
+}
+
+/// Whether a response was marked as `Sensitive`
+fn is_sensitive(res: &Response) -> bool {
+    res.extensions.contains::<Sensitive>()
+}
+
+/// **Compression Middleware**
+///
+/// Currently either compresses using brotli, gzip or deflate algorithms. The algorithm is
+/// chosen by evaluating the `AcceptEncoding` header sent by the client.
+///
+/// # Example
+/// ```rust,no_run
+/// extern crate iron;
+/// extern crate iron_pack;
+///
+/// use iron::prelude::*;
+/// use iron_pack::CompressionMiddleware;
+///
+/// fn a_lot_of_batman(_: &mut Request) -> IronResult<Response> {
+///     let nana = "Na".repeat(5000);
+///     Ok(Response::with((iron::status::Ok, format!("{}, Batman!", nana))))
+/// }
+///
+/// fn main() {
+///     let mut chain = Chain::new(a_lot_of_batman);
+///     chain.link_after(CompressionMiddleware::new());
+///     Iron::new(chain).http("localhost:3000").unwrap();
+/// }
+/// ```
+pub struct CompressionMiddleware {
+    compressor: Compressor,
+    predicates: Vec<Predicate>,
+}
+
+impl CompressionMiddleware {
+    /// Creates the middleware using the default configuration
+    pub fn new() -> CompressionMiddleware {
+        CompressionMiddleware::with_config(Config::default())
+    }
+
+    /// Creates the middleware using a custom configuration
+    pub fn with_config(config: Config) -> CompressionMiddleware {
+        CompressionMiddleware {
+            predicates: Vec::new(),
+            compressor: Compressor::with_config(config),
+        }
+    }
+
+    /// Only compress responses `predicate` returns true for. Other responses are skipped with
+    /// `SkipReason::Predicate(name)`. Predicates are evaluated in the order they are added,
+    /// before the built-in rules.
+    ///
+    /// # Example
+    /// ```rust
+    /// extern crate iron;
+    /// extern crate iron_pack;
+    ///
+    /// use iron::headers::{Host, UserAgent};
+    /// use iron_pack::CompressionMiddleware;
+    ///
+    /// # fn main() {
+    /// let middleware = CompressionMiddleware::new()
+    ///     .include("public-host", |req, _| {
+    ///         req.headers.get::<Host>().map_or(false, |host| host.hostname == "www.example.com")
+    ///     })
+    ///     .exclude("health-check", |req, _| {
+    ///         req.headers.get::<UserAgent>().map_or(false, |agent| agent.starts_with("HealthChecker"))
+    ///     });
+    /// # }
+    /// ```
+    pub fn include<F>(mut self, name: &'static str, predicate: F) -> CompressionMiddleware
+        where F: Fn(&Request, &Response) -> bool + Send + Sync + 'static {
+        self.predicates.push(Predicate { name, include: true, test: Box::new(predicate) });
+        self
+    }
+
+    /// Do not compress responses `predicate` returns true for. They are skipped with
+    /// `SkipReason::Predicate(name)`.
+    pub fn exclude<F>(mut self, name: &'static str, predicate: F) -> CompressionMiddleware
+        where F: Fn(&Request, &Response) -> bool + Send + Sync + 'static {
+        self.predicates.push(Predicate { name, include: false, test: Box::new(predicate) });
+        self
+    }
+
+    /// The name of the first predicate that rules the response out, if any
+    fn excluded_by(&self, req: &Request, res: &Response) -> Option<&'static str> {
+        self.predicates
+            .iter()
+            .find(|predicate| (predicate.test)(req, res) != predicate.include)
+            .map(|predicate| predicate.name)
+    }
+
+    /// What the pipeline needs to know about an iron request and its response
+    fn facts<'a>(&self, req: &'a Request, res: &'a Response) -> Facts<'a> {
+        Facts {
+            exchange: exchange(req, res),
+            te: req.headers.get_raw("TE"),
+            content_encoding: res.headers.get::<ContentEncoding>().map(|content_encoding| &content_encoding.0[..]),
+            path: req.url.path(),
+            route: req.extensions.get::<RouteId>().map(|route| route.as_str()),
+            excluded_by: self.excluded_by(req, res),
+        }
+    }
+
+    /// Decides which of the precompressed variants of `res` to send, if any, see
+    /// `Compressor::decide_among`
+    pub(crate) fn choose_variant(&self, req: &Request, res: &Response, available: &[CompressionEncoding]) -> Decision {
+        self.compressor.decide_among(&self.facts(req, res), available)
+    }
+
+    /// Apply `rule` to requests whose URL path matches `pattern`. `*` matches any part of a path
+    /// segment and `**` any number of segments, e.g. `/static/**` or `/reports/*.csv`. The first
+    /// matching rule, in the order they are added, applies.
+    pub fn rule(self, pattern: &str, rule: Rule) -> CompressionMiddleware {
+        self.add_rule(Matcher::path(pattern), rule)
+    }
+
+    /// Apply `rule` to requests whose `RouteId` extension equals `route_id`
+    pub fn route_rule<S: Into<String>>(self, route_id: S, rule: Rule) -> CompressionMiddleware {
+        self.add_rule(Matcher::Route(route_id.into()), rule)
+    }
+
+    fn add_rule(mut self, matcher: Matcher, rule: Rule) -> CompressionMiddleware {
+        self.compressor.add_rule(matcher, rule);
+        self
+    }
+
+    /// A handle to the counters of this middleware, which stays valid after the middleware is
+    /// linked into a chain
+    pub fn stats(&self) -> Stats {
+        self.compressor.stats()
+    }
+}
+
+impl Default for CompressionMiddleware {
+    fn default() -> CompressionMiddleware {
+        CompressionMiddleware::new()
+    }
+}
+
+impl AfterMiddleware for CompressionMiddleware {
+
+    /// Implementation of the compression middleware
+    fn after(&self, req: &mut Request, mut res: Response) -> IronResult<Response> {
+        let plan = self.compressor.plan(&self.facts(req, &res));
+        let stats = &self.compressor.stats;
+
+        match plan {
+            Plan::Compress { encoding, coding, level, permit } => {
+                // The length of a transcoded body is the encoded one, its decoded length is unknown
+                let (content_length, body) = match coding {
+                    Coding::Transcoded(from) => (None, Box::new(DecodedBody(from, res.body.take().unwrap())) as Box<WriteBody>),
+                    _ => (res.headers.get::<ContentLength>().map(|&ContentLength(length)| length), res.body.take().unwrap()),
+                };
+                let body_encoding = self.compressor.body_encoding(encoding, level, content_length);
+                let mut encoded: Box<WriteBody> = Box::new(EncodedBody(body_encoding, body));
+                let decision = Decision::Compress(encoding);
+                let in_flight = stats.start_compression();
+
+                // Digests need the whole compressed body before the headers are sent
+                if coding != Coding::Transfer && self.compressor.config.has_digests() {
+                    let start = Instant::now();
+                    let compressed = buffer(&mut *encoded, 0).map_err(|err| IronError::new(err, status::InternalServerError))?;
+                    if let Some(length) = content_length {
+                        stats.record_throughput(length, start.elapsed());
+                    }
+                    self.compressor.finish(&req.headers, &decision, coding, content_length, Some(&compressed), &mut res.headers);
+                    res.body = Some(Box::new(compressed));
+                } else {
+                    self.compressor.finish(&req.headers, &decision, coding, content_length, None, &mut res.headers);
+                    res.body = Some(Box::new(TrackedBody {
+                        _permit: permit,
+                        _in_flight: in_flight,
+                        stats: stats.clone(),
+                        content_length,
+                        body: encoded,
+                    }));
+                }
+            }
+            Plan::Skip { reason, coding } => {
+                if let Coding::Transcoded(from) = coding {
+                    res.body = res.body.take().map(|body| Box::new(DecodedBody(from, body)) as Box<WriteBody>);
+                }
+                self.compressor.finish(&req.headers, &Decision::Skip(reason), coding, None, None, &mut res.headers);
+            }
+        }
+
+        Ok(res)
+    }
+
+    /// Compresses the response of an error the same way, and keeps the error propagating. If
+    /// the compression fails, its error is propagated instead.
+    fn catch(&self, req: &mut Request, mut err: IronError) -> IronResult<Response> {
+        let res = mem::replace(&mut err.response, Response::new());
+        err.response = self.after(req, res)?;
+        Err(err)
+    }
+}
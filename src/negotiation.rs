@@ -0,0 +1,351 @@
+//! Decides whether and how a response is compressed, based on plain header values.
+
+use std::fmt;
+use hyper::header::{Encoding, QualityItem, Quality};
+
+use accept_encoding;
+use config::{Config, OversizePolicy};
+
+pub use encoding::CompressionEncoding;
+
+/// How the encoding is chosen among the ones the client accepts
+#[derive(PartialEq, Clone, Debug, Default)]
+pub enum Strategy {
+    /// The encoding the client accepts with the highest quality. Ties are broken by the
+    /// configured priority. This is the default.
+    #[default]
+    ClientQuality,
+    /// The first encoding listed in the `Accept-Encoding` header, whatever its quality. Only
+    /// encodings refused with a quality of 0 are passed over.
+    ClientOrder,
+    /// The first encoding of the configured priority the client accepts at all
+    ServerOrder,
+    /// The encoding with the highest product of the client's quality and a server weight.
+    /// Encodings without a weight have a weight of 1, ties are broken by the configured
+    /// priority. E.g. a weight of 0.5 for brotli makes gzip win unless the client accepts gzip
+    /// with less than half the quality.
+    Weighted(Vec<(CompressionEncoding, f32)>),
+}
+
+/// The reason why a response is sent without compression
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum SkipReason {
+    /// The response has no body
+    NoBody,
+    /// The response already has a `Content-Encoding`
+    AlreadyEncoded,
+    /// The response has no `Content-Length`
+    UnknownLength,
+    /// The response is smaller than the configured minimum
+    TooSmall,
+    /// The response is larger than the configured maximum
+    TooLarge,
+    /// The request was sent on behalf of another site, see `BreachMitigation::skip_cross_site`
+    CrossSite,
+    /// The response was marked as `Sensitive`
+    Sensitive,
+    /// A predicate registered with `CompressionMiddleware::include` or `exclude` ruled the
+    /// response out. Holds the name of the predicate.
+    Predicate(&'static str),
+    /// A `Rule::never` rule matched the request
+    Rule,
+    /// The client did not send an `Accept-Encoding` header
+    NoAcceptEncoding,
+    /// Not a single content-coding could be parsed from the `Accept-Encoding` header
+    MalformedAcceptEncoding,
+    /// The client refused every supported encoding with a quality of 0
+    QualityZero,
+    /// The client prefers none of the supported encodings
+    NoAcceptableEncoding,
+    /// The client accepts no supported transfer-coding in its `TE` header
+    NoTransferCoding,
+    /// The concurrency limit of the middleware was reached
+    Saturated,
+    /// BREACH padding is enabled, and the client accepts none of the encodings that can be
+    /// padded. Only gzip can be padded.
+    Padding,
+}
+
+impl SkipReason {
+    /// A short, kebab-cased name of the reason that can be used in logs and metrics
+    pub fn name(&self) -> &'static str {
+        match *self {
+            SkipReason::NoBody => "no-body",
+            SkipReason::AlreadyEncoded => "already-encoded",
+            SkipReason::UnknownLength => "unknown-length",
+            SkipReason::TooSmall => "too-small",
+            SkipReason::TooLarge => "too-large",
+            SkipReason::CrossSite => "cross-site",
+            SkipReason::Sensitive => "sensitive",
+            SkipReason::Predicate(name) => name,
+            SkipReason::Rule => "rule",
+            SkipReason::NoAcceptEncoding => "no-accept-encoding",
+            SkipReason::MalformedAcceptEncoding => "malformed-accept-encoding",
+            SkipReason::QualityZero => "quality-zero",
+            SkipReason::NoAcceptableEncoding => "no-acceptable-encoding",
+            SkipReason::NoTransferCoding => "no-transfer-coding",
+            SkipReason::Saturated => "saturated",
+            SkipReason::Padding => "padding",
+        }
+    }
+}
+
+impl fmt::Display for SkipReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// The outcome of negotiating the compression of a response
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum Decision {
+    /// The response should be compressed using the given encoding
+    Compress(CompressionEncoding),
+    /// The response should be sent as is
+    Skip(SkipReason),
+}
+
+impl Decision {
+    /// The chosen encoding, if the response should be compressed
+    pub fn encoding(&self) -> Option<CompressionEncoding> {
+        match *self {
+            Decision::Compress(encoding) => Some(encoding),
+            Decision::Skip(_) => None,
+        }
+    }
+}
+
+/// The `*` coding is answered with the first enabled encoding that is understood most widely
+fn is_wildcard_encoding(encoding: &CompressionEncoding) -> bool {
+    let widest = [
+        #[cfg(feature = "gzip")]
+        CompressionEncoding::Gzip,
+        #[cfg(feature = "deflate")]
+        CompressionEncoding::Deflate,
+        #[cfg(feature = "brotli")]
+        CompressionEncoding::Brotli,
+    ];
+    widest.first() == Some(encoding)
+}
+
+pub fn encoding_matches_header(encoding: &CompressionEncoding, header: &Encoding) -> bool {
+    *header == get_header(encoding) || (*header == Encoding::EncodingExt(String::from("*")) && is_wildcard_encoding(encoding))
+}
+
+pub fn get_header(encoding: &CompressionEncoding) -> Encoding {
+    match *encoding {
+        #[cfg(feature = "brotli")]
+        CompressionEncoding::Brotli => Encoding::EncodingExt(String::from("br")),
+        #[cfg(feature = "deflate")]
+        CompressionEncoding::Deflate => Encoding::Deflate,
+        #[cfg(feature = "gzip")]
+        CompressionEncoding::Gzip => Encoding::Gzip,
+        #[cfg(feature = "zstd")]
+        CompressionEncoding::Zstd => Encoding::EncodingExt(String::from("zstd")),
+    }
+}
+
+/// Parses the raw `Accept-Encoding` header lines. hyper's typed parser silently turns elements
+/// like `gzip ; q = 1` into unknown codings and panics on empty ones, so the lenient parser is
+/// used for every request. It yields the same result for well-formed headers.
+fn accept_encoding(raw: Option<&[Vec<u8>]>) -> Result<Vec<QualityItem<Encoding>>, SkipReason> {
+    match raw {
+        Some(raw) => accept_encoding::parse_lenient(raw).map_err(|_| SkipReason::MalformedAcceptEncoding),
+        None => Err(SkipReason::NoAcceptEncoding),
+    }
+}
+
+/// The quality the client accepts an encoding with. An explicit coding takes precedence over `*`.
+fn quality_of(encoding: &CompressionEncoding, quality_items: &[QualityItem<Encoding>]) -> Option<Quality> {
+    let header = get_header(encoding);
+    quality_items
+        .iter()
+        .find(|qi| qi.item == header)
+        .or_else(|| quality_items.iter().find(|qi| encoding_matches_header(encoding, &qi.item)))
+        .map(|qi| qi.quality)
+}
+
+fn choose(quality_items: &[QualityItem<Encoding>], priority: &[CompressionEncoding], strategy: &Strategy) -> Option<CompressionEncoding> {
+    let acceptable = |encoding: &&CompressionEncoding| quality_of(encoding, quality_items).map_or(false, |quality| quality != Quality(0));
+
+    match *strategy {
+        Strategy::ClientQuality => {
+            let max_quality = quality_items.iter().map(|qi| qi.quality).max().unwrap_or(Quality(0));
+            let preferred: Vec<&QualityItem<Encoding>> = quality_items
+                .iter()
+                .filter(|qi| qi.quality != Quality(0) && qi.quality == max_quality)
+                .collect();
+
+            priority
+                .iter()
+                .find(|ce| preferred.iter().any(|qi| encoding_matches_header(ce, &qi.item)))
+                .cloned()
+        }
+        Strategy::ClientOrder => quality_items
+            .iter()
+            .filter(|qi| qi.quality != Quality(0))
+            .filter_map(|qi| priority.iter().find(|ce| encoding_matches_header(ce, &qi.item)))
+            .find(acceptable)
+            .cloned(),
+        Strategy::ServerOrder => priority.iter().find(acceptable).cloned(),
+        Strategy::Weighted(ref weights) => {
+            let mut best: Option<(CompressionEncoding, f32)> = None;
+            for encoding in priority {
+                let quality = match quality_of(encoding, quality_items) {
+                    Some(Quality(quality)) => f32::from(quality) / 1000.0,
+                    None => continue,
+                };
+                let weight = weights.iter().find(|&&(weighted, _)| weighted == *encoding).map_or(1.0, |&(_, weight)| weight);
+                let score = quality * weight;
+                if score > 0.0 && best.map_or(true, |(_, best_score)| score > best_score) {
+                    best = Some((*encoding, score));
+                }
+            }
+            best.map(|(encoding, _)| encoding)
+        }
+    }
+}
+
+fn which_compression(quality_items: &[QualityItem<Encoding>], priority: &[CompressionEncoding], strategy: &Strategy) -> Result<CompressionEncoding, SkipReason> {
+    if let Some(encoding) = choose(quality_items, priority, strategy) {
+        return Ok(encoding);
+    }
+
+    let refused = priority
+        .iter()
+        .all(|ce| quality_items.iter().all(|qi| !encoding_matches_header(ce, &qi.item) || qi.quality == Quality(0)));
+    let mentioned = priority
+        .iter()
+        .any(|ce| quality_items.iter().any(|qi| encoding_matches_header(ce, &qi.item)));
+    if mentioned && refused {
+        Err(SkipReason::QualityZero)
+    } else {
+        Err(SkipReason::NoAcceptableEncoding)
+    }
+}
+
+/// Only gzip can be padded, so it is the only encoding offered while padding is enabled
+fn padded_priority(config: &Config) -> Vec<CompressionEncoding> {
+    #[cfg(feature = "padding")]
+    {
+        if config.breach.pads() {
+            return config.priority.iter().cloned().filter(|&encoding| encoding == CompressionEncoding::Gzip).collect();
+        }
+    }
+    config.priority.clone()
+}
+
+/// The parts of a request and its response the compression decision is based on
+#[derive(Clone, Debug, Default)]
+pub struct Exchange<'a> {
+    /// The raw lines of the `Accept-Encoding` request header, if sent
+    pub accept_encoding: Option<&'a [Vec<u8>]>,
+    /// Whether the response has a body
+    pub has_body: bool,
+    /// Whether the response already has a `Content-Encoding`
+    pub content_encoded: bool,
+    /// The `Content-Length` of the response, if known
+    pub content_length: Option<u64>,
+    /// Whether the request was sent on behalf of another site
+    pub cross_site: bool,
+    /// Whether the response reflects secrets next to user input
+    pub sensitive: bool,
+}
+
+/// The reason why a response can't be compressed at all, whichever coding the client accepts
+fn check_exchange(exchange: &Exchange, config: &Config) -> Result<(), SkipReason> {
+    if !exchange.has_body {
+        return Err(SkipReason::NoBody);
+    }
+    if exchange.content_encoded {
+        return Err(SkipReason::AlreadyEncoded);
+    }
+    match exchange.content_length {
+        // Neither `min_bytes` nor `max_bytes` could be checked before the body is sent
+        None => return Err(SkipReason::UnknownLength),
+        Some(length) if length < config.min_bytes => return Err(SkipReason::TooSmall),
+        Some(length) if config.max_bytes.map_or(false, |(max_bytes, policy)| policy == OversizePolicy::Skip && length > max_bytes) => {
+            return Err(SkipReason::TooLarge);
+        }
+        Some(_) => {}
+    }
+    if config.breach.skip_sensitive && exchange.sensitive {
+        return Err(SkipReason::Sensitive);
+    }
+    if config.breach.skip_cross_site && exchange.cross_site {
+        return Err(SkipReason::CrossSite);
+    }
+    Ok(())
+}
+
+/// Decides whether a response should be compressed, and using which encoding, independent of
+/// the web framework in use.
+pub fn negotiate_exchange(exchange: &Exchange, config: &Config) -> Decision {
+    let priority = padded_priority(config);
+    let negotiated = check_exchange(exchange, config)
+        .and_then(|_| accept_encoding(exchange.accept_encoding))
+        .and_then(|quality_items| match which_compression(&quality_items, &priority, &config.strategy) {
+            // Tell apart the clients that only accept encodings which can't be padded
+            Err(_) if config.breach.pads() && which_compression(&quality_items, &config.priority, &config.strategy).is_ok() => {
+                Err(SkipReason::Padding)
+            }
+            negotiated => negotiated,
+        });
+    match negotiated {
+        Ok(encoding) => Decision::Compress(encoding),
+        Err(reason) => Decision::Skip(reason),
+    }
+}
+
+/// The encoding of a response with the given `Content-Encoding`, if it is a single supported
+/// content-coding
+pub fn encoding_of(content_encoding: &[Encoding]) -> Option<CompressionEncoding> {
+    match *content_encoding {
+        [ref coding] => CompressionEncoding::all().into_iter().find(|encoding| get_header(encoding) == *coding),
+        _ => None,
+    }
+}
+
+/// Decides how a response that is already encoded using `from` is transcoded. Returns `None` if
+/// the client accepts `from`, or sends no parsable `Accept-Encoding` header, so the response can
+/// be sent as is. Otherwise the decision tells the encoding to re-encode the decoded body with,
+/// or why it is sent decoded. The exchange describes the encoded response.
+pub fn negotiate_transcoding(from: CompressionEncoding, exchange: &Exchange, config: &Config) -> Option<Decision> {
+    let quality_items = accept_encoding(exchange.accept_encoding).ok()?;
+    if quality_of(&from, &quality_items).map_or(false, |quality| quality != Quality(0)) {
+        return None;
+    }
+    let decoded = Exchange { content_encoded: false, ..exchange.clone() };
+    Some(negotiate_exchange(&decoded, config))
+}
+
+/// Decides whether a response should be compressed as transfer-coding, based on the raw lines
+/// of the `TE` request header instead of the `Accept-Encoding` header of the exchange. Only
+/// gzip and deflate are transfer-codings.
+pub fn negotiate_transfer_coding(te: Option<&[Vec<u8>]>, exchange: &Exchange, config: &Config) -> Decision {
+    let priority: Vec<CompressionEncoding> = padded_priority(config).into_iter().filter(is_transfer_coding).collect();
+    let negotiated = check_exchange(exchange, config).and_then(|_| {
+        // `trailers` and other codings are no preference, only the supported ones are compared
+        te.and_then(|raw| accept_encoding::parse_lenient(raw).ok())
+            .map(|quality_items| quality_items.into_iter().filter(|qi| priority.iter().any(|ce| encoding_matches_header(ce, &qi.item))).collect::<Vec<_>>())
+            .and_then(|quality_items| choose(&quality_items, &priority, &config.strategy))
+            .ok_or(SkipReason::NoTransferCoding)
+    });
+    match negotiated {
+        Ok(encoding) => Decision::Compress(encoding),
+        Err(reason) => Decision::Skip(reason),
+    }
+}
+
+fn is_transfer_coding(encoding: &CompressionEncoding) -> bool {
+    match *encoding {
+        #[cfg(feature = "brotli")]
+        CompressionEncoding::Brotli => false,
+        #[cfg(feature = "deflate")]
+        CompressionEncoding::Deflate => true,
+        #[cfg(feature = "gzip")]
+        CompressionEncoding::Gzip => true,
+        #[cfg(feature = "zstd")]
+        CompressionEncoding::Zstd => false,
+    }
+}
@@ -0,0 +1,143 @@
+//! pigz-style gzip compression of large bodies on a pool of worker threads.
+//!
+//! The input is split into blocks that are deflated independently. Every block but the last ends
+//! with a sync flush, so the compressed blocks are byte-aligned and can be concatenated into a
+//! single, regular gzip member.
+
+use std::collections::VecDeque;
+use std::io;
+use std::io::Write;
+use std::mem;
+use std::sync::{mpsc, Arc};
+use flate2::{Compress, Compression, Crc, FlushCompress};
+
+use codec::GzipHeader;
+use pool::ThreadPool;
+
+/// An empty, final block using fixed huffman codes
+const FINAL_BLOCK: [u8; 2] = [0x03, 0x00];
+
+/// Configuration of the parallel gzip compression of large responses
+#[derive(Clone, Debug, PartialEq)]
+pub struct ParallelGzip {
+    /// Responses with a `Content-Length` of at least this many bytes are compressed in parallel
+    pub min_bytes: u64,
+    /// The number of worker threads
+    pub threads: usize,
+    /// The size of the blocks that are compressed independently
+    pub block_size: usize,
+}
+
+impl Default for ParallelGzip {
+    fn default() -> ParallelGzip {
+        ParallelGzip {
+            min_bytes: 4 * 1024 * 1024,
+            threads: 4,
+            block_size: 128 * 1024,
+        }
+    }
+}
+
+fn deflate_block(data: &[u8], level: Compression) -> io::Result<Vec<u8>> {
+    let mut compress = Compress::new(level, false);
+    let mut compressed = Vec::with_capacity(data.len() / 2 + 64);
+
+    loop {
+        if compressed.len() == compressed.capacity() {
+            let additional = compressed.capacity();
+            compressed.reserve(additional);
+        }
+        let consumed = compress.total_in() as usize;
+        compress.compress_vec(&data[consumed..], &mut compressed, FlushCompress::Sync)
+            .map_err(|err| io::Error::new(io::ErrorKind::Other, err))?;
+        if compress.total_in() as usize == data.len() && compressed.len() < compressed.capacity() {
+            return Ok(compressed);
+        }
+    }
+}
+
+/// A gzip encoder that compresses blocks of its input on a thread pool
+pub struct ParallelGzipEncoder<W: Write> {
+    inner: W,
+    pool: Arc<ThreadPool>,
+    block_size: usize,
+    level: Compression,
+    max_pending: usize,
+    block: Vec<u8>,
+    crc: Crc,
+    pending: VecDeque<mpsc::Receiver<io::Result<Vec<u8>>>>,
+}
+
+impl<W: Write> ParallelGzipEncoder<W> {
+    pub fn new(mut inner: W, pool: Arc<ThreadPool>, config: &ParallelGzip, level: u32, header: &GzipHeader) -> io::Result<ParallelGzipEncoder<W>> {
+        inner.write_all(&header.to_bytes())?;
+        Ok(ParallelGzipEncoder {
+            inner,
+            pool,
+            block_size: config.block_size.max(1),
+            level: Compression::new(level.min(9)),
+            max_pending: config.threads.max(1) * 2,
+            block: Vec::with_capacity(config.block_size),
+            crc: Crc::new(),
+            pending: VecDeque::new(),
+        })
+    }
+
+    fn submit_block(&mut self) -> io::Result<()> {
+        let block = mem::replace(&mut self.block, Vec::with_capacity(self.block_size));
+        let (sender, receiver) = mpsc::channel();
+        let level = self.level;
+        self.pool.execute(move || {
+            let _ = sender.send(deflate_block(&block, level));
+        });
+        self.pending.push_back(receiver);
+
+        while self.pending.len() > self.max_pending {
+            self.write_next_block()?;
+        }
+        Ok(())
+    }
+
+    fn write_next_block(&mut self) -> io::Result<()> {
+        if let Some(receiver) = self.pending.pop_front() {
+            let compressed = receiver
+                .recv()
+                .map_err(|_| io::Error::new(io::ErrorKind::Other, "compression worker failed"))??;
+            self.inner.write_all(&compressed)?;
+        }
+        Ok(())
+    }
+
+    /// Writes the remaining blocks and the gzip trailer and returns the wrapped writer
+    pub fn finish(mut self) -> io::Result<W> {
+        if !self.block.is_empty() {
+            self.submit_block()?;
+        }
+        while !self.pending.is_empty() {
+            self.write_next_block()?;
+        }
+
+        self.inner.write_all(&FINAL_BLOCK)?;
+        let (crc, amount) = (self.crc.sum(), self.crc.amount());
+        self.inner.write_all(&[crc as u8, (crc >> 8) as u8, (crc >> 16) as u8, (crc >> 24) as u8])?;
+        self.inner.write_all(&[amount as u8, (amount >> 8) as u8, (amount >> 16) as u8, (amount >> 24) as u8])?;
+        Ok(self.inner)
+    }
+}
+
+impl<W: Write> Write for ParallelGzipEncoder<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let length = buf.len().min(self.block_size - self.block.len());
+        self.block.extend_from_slice(&buf[..length]);
+        self.crc.update(&buf[..length]);
+
+        if self.block.len() == self.block_size {
+            self.submit_block()?;
+        }
+        Ok(length)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
@@ -0,0 +1,60 @@
+//! A fixed-size pool of worker threads.
+
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+
+type Job = Box<FnOnce() + Send + 'static>;
+
+/// Runs jobs on a fixed number of threads. Dropping the pool waits for queued jobs to finish.
+pub struct ThreadPool {
+    sender: Option<Mutex<mpsc::Sender<Job>>>,
+    workers: Vec<thread::JoinHandle<()>>,
+}
+
+impl ThreadPool {
+    pub fn new(threads: usize) -> ThreadPool {
+        let (sender, receiver) = mpsc::channel::<Job>();
+        let receiver = Arc::new(Mutex::new(receiver));
+
+        let workers = (0..threads.max(1))
+            .map(|index| {
+                let receiver = receiver.clone();
+                thread::Builder::new()
+                    .name(format!("iron-pack-worker-{}", index))
+                    .spawn(move || loop {
+                        let job = match receiver.lock() {
+                            Ok(receiver) => receiver.recv(),
+                            Err(_) => return,
+                        };
+                        match job {
+                            Ok(job) => {
+                                // A panicking job only loses its result, the worker keeps running
+                                let _ = panic::catch_unwind(AssertUnwindSafe(job));
+                            }
+                            Err(_) => return,
+                        }
+                    })
+                    .expect("failed to spawn compression worker")
+            })
+            .collect();
+
+        ThreadPool { sender: Some(Mutex::new(sender)), workers }
+    }
+
+    pub fn execute<F: FnOnce() + Send + 'static>(&self, job: F) {
+        if let Some(ref sender) = self.sender {
+            // Sending only fails once all workers are gone, the caller notices the missing result
+            let _ = sender.lock().map(|sender| sender.send(Box::new(job)));
+        }
+    }
+}
+
+impl Drop for ThreadPool {
+    fn drop(&mut self) {
+        self.sender.take();
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
@@ -0,0 +1,183 @@
+//! Writes precompressed sidecars of static files ahead of time, to be served by `StaticFiles`.
+//!
+//! Sidecars use the levels of the middleware by default, or any `RuleLevel` of each codec. gzip
+//! sidecars have a deterministic header, so unchanged files produce unchanged sidecars. zstd sidecars are opt-in and need the `zstd` feature. Symbolic
+//! links are not followed.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use mime_guess;
+
+#[cfg(any(feature = "brotli", feature = "gzip", feature = "zstd"))]
+use codec;
+use config::Config;
+use content_type;
+#[cfg(any(feature = "brotli", feature = "gzip", feature = "zstd"))]
+use negotiation::CompressionEncoding;
+use rules::RuleLevel;
+
+/// The extensions of sidecars in every format, including disabled ones
+const SIDECAR_EXTENSIONS: [&str; 3] = ["br", "gz", "zst"];
+
+/// The kind of a sidecar file
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum SidecarFormat {
+    /// `.br`
+    #[cfg(feature = "brotli")]
+    Brotli,
+    /// `.gz`
+    #[cfg(feature = "gzip")]
+    Gzip,
+    /// `.zst`
+    #[cfg(feature = "zstd")]
+    Zstd,
+}
+
+impl SidecarFormat {
+    /// All formats enabled through cargo features
+    pub fn all() -> Vec<SidecarFormat> {
+        let mut formats = vec![];
+        #[cfg(feature = "brotli")]
+        formats.push(SidecarFormat::Brotli);
+        #[cfg(feature = "gzip")]
+        formats.push(SidecarFormat::Gzip);
+        #[cfg(feature = "zstd")]
+        formats.push(SidecarFormat::Zstd);
+        formats
+    }
+
+    /// The extension appended to the name of the original file
+    pub fn extension(&self) -> &'static str {
+        match *self {
+            #[cfg(feature = "brotli")]
+            SidecarFormat::Brotli => "br",
+            #[cfg(feature = "gzip")]
+            SidecarFormat::Gzip => "gz",
+            #[cfg(feature = "zstd")]
+            SidecarFormat::Zstd => "zst",
+        }
+    }
+
+    #[cfg_attr(not(any(feature = "brotli", feature = "gzip", feature = "zstd")), allow(unused_variables))]
+    fn compress(&self, data: &[u8], level: RuleLevel) -> io::Result<Vec<u8>> {
+        match *self {
+            #[cfg(feature = "brotli")]
+            SidecarFormat::Brotli => codec::encode_with_level(CompressionEncoding::Brotli, level.of(CompressionEncoding::Brotli), data),
+            #[cfg(feature = "gzip")]
+            SidecarFormat::Gzip => codec::encode_gzip(level.of(CompressionEncoding::Gzip), &codec::GzipHeader::default(), data),
+            #[cfg(feature = "zstd")]
+            SidecarFormat::Zstd => codec::encode_with_level(CompressionEncoding::Zstd, level.of(CompressionEncoding::Zstd), data),
+        }
+    }
+}
+
+/// Which sidecars are written for which files
+#[derive(Clone, Debug)]
+pub struct Precompress {
+    /// The sidecars to write (default: brotli and gzip, when enabled)
+    pub formats: Vec<SidecarFormat>,
+    /// The level of each codec to compress with (default: `RuleLevel::Default`)
+    pub level: RuleLevel,
+    /// Files smaller than this are skipped (default: the one of `Config::min_bytes`)
+    pub min_bytes: u64,
+    /// Sidecars have to be at least this fraction smaller than the original file to be written
+    /// (default: 0.05)
+    pub min_saving: f64,
+    /// Compress files of any content type, not only the ones worth compressing (default: false)
+    pub all_types: bool,
+}
+
+impl Default for Precompress {
+    fn default() -> Precompress {
+        Precompress {
+            formats: SidecarFormat::all().into_iter().filter(|format| format.extension() != "zst").collect(),
+            level: RuleLevel::Default,
+            min_bytes: Config::default().min_bytes,
+            min_saving: 0.05,
+            all_types: false,
+        }
+    }
+}
+
+/// The outcome of compressing a file in one format
+#[derive(Clone, Debug)]
+pub struct SidecarReport {
+    pub format: SidecarFormat,
+    /// The compressed size
+    pub length: u64,
+    /// Whether the sidecar was written. Sidecars that do not pay off are not written, and
+    /// removed if they exist from an earlier run.
+    pub written: bool,
+}
+
+/// The outcome of precompressing a single file
+#[derive(Clone, Debug)]
+pub struct FileReport {
+    pub path: PathBuf,
+    /// The size of the original file
+    pub length: u64,
+    /// The sidecars, or why the file was skipped
+    pub outcome: Result<Vec<SidecarReport>, &'static str>,
+}
+
+impl Precompress {
+    /// Writes the sidecars of every file below `dir`. Existing sidecars are not compressed again,
+    /// and symbolic links are skipped.
+    pub fn dir(&self, dir: &Path) -> io::Result<Vec<FileReport>> {
+        let mut reports = Vec::new();
+        let mut entries: Vec<(PathBuf, fs::FileType)> = fs::read_dir(dir)?
+            .map(|entry| entry.and_then(|entry| Ok((entry.path(), entry.file_type()?))))
+            .collect::<io::Result<_>>()?;
+        entries.sort_by(|a, b| a.0.cmp(&b.0));
+        for (path, file_type) in entries {
+            if file_type.is_dir() {
+                reports.extend(self.dir(&path)?);
+            } else if file_type.is_file() && !is_sidecar(&path) {
+                reports.push(self.file(&path)?);
+            }
+        }
+        Ok(reports)
+    }
+
+    /// Writes the sidecars of a single file
+    pub fn file(&self, path: &Path) -> io::Result<FileReport> {
+        let length = fs::metadata(path)?.len();
+        let report = |outcome| FileReport { path: path.to_owned(), length, outcome };
+        if length < self.min_bytes {
+            return Ok(report(Err("too-small")));
+        }
+        if !self.all_types && !content_type::is_compressible(&mime_guess::guess_mime_type(path)) {
+            return Ok(report(Err("content-type")));
+        }
+
+        let data = fs::read(path)?;
+        let mut sidecars = Vec::new();
+        for format in &self.formats {
+            let compressed = format.compress(&data, self.level)?;
+            let sidecar = sidecar_path(path, format);
+            let written = (compressed.len() as f64) <= length as f64 * (1.0 - self.min_saving);
+            if written {
+                fs::write(&sidecar, &compressed)?;
+            } else if sidecar.is_file() {
+                fs::remove_file(&sidecar)?;
+            }
+            sidecars.push(SidecarReport { format: *format, length: compressed.len() as u64, written });
+        }
+        Ok(report(Ok(sidecars)))
+    }
+}
+
+/// Whether a file is the sidecar of another one, in any format
+fn is_sidecar(path: &Path) -> bool {
+    let extension = path.extension().and_then(|extension| extension.to_str());
+    SIDECAR_EXTENSIONS.iter().any(|&sidecar| extension == Some(sidecar)) && path.with_extension("").is_file()
+}
+
+/// The path of the sidecar of `path`, e.g. `app.js.br`
+pub fn sidecar_path(path: &Path, format: &SidecarFormat) -> PathBuf {
+    let mut sidecar = path.as_os_str().to_owned();
+    sidecar.push(".");
+    sidecar.push(format.extension());
+    PathBuf::from(sidecar)
+}
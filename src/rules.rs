@@ -0,0 +1,132 @@
+//! Per-path and per-route compression rules.
+
+use codec::{default_level, max_level};
+use negotiation::CompressionEncoding;
+
+/// A level a rule compresses with, relative to the levels of the chosen encoding
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum RuleLevel {
+    /// The fastest level of the encoding
+    Fastest,
+    /// The level the encoding uses without rules
+    Default,
+    /// The highest level of the encoding
+    Max,
+    /// A native level of the encoding, see `codec`
+    Exact(u32),
+}
+
+impl RuleLevel {
+    pub(crate) fn of(&self, encoding: CompressionEncoding) -> u32 {
+        match *self {
+            RuleLevel::Fastest => 1,
+            RuleLevel::Default => default_level(encoding),
+            RuleLevel::Max => max_level(encoding),
+            RuleLevel::Exact(level) => level.min(max_level(encoding)),
+        }
+    }
+}
+
+/// **Compression Rule**
+///
+/// What happens to the responses of the requests a rule matches. A rule can prevent compression,
+/// prefer an encoding over the configured priority and fix the compression level.
+///
+/// # Example
+/// ```rust
+/// use iron_pack::{CompressionEncoding, CompressionMiddleware, Rule, RuleLevel};
+///
+/// # #[cfg(all(feature = "gzip", feature = "brotli"))]
+/// # fn main() {
+/// let middleware = CompressionMiddleware::new()
+///     .rule("/static/**", Rule::prefer(CompressionEncoding::Brotli).level(RuleLevel::Max))
+///     .rule("/api/stream/**", Rule::never())
+///     .rule("/download/**", Rule::prefer(CompressionEncoding::Gzip).level(RuleLevel::Fastest));
+/// # }
+/// # #[cfg(not(all(feature = "gzip", feature = "brotli")))]
+/// # fn main() {}
+/// ```
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub struct Rule {
+    pub(crate) compress: bool,
+    pub(crate) encoding: Option<CompressionEncoding>,
+    pub(crate) level: Option<RuleLevel>,
+}
+
+impl Rule {
+    /// Compress as usual. Useful to exempt paths from a broader rule that comes later.
+    pub fn compress() -> Rule {
+        Rule { compress: true, encoding: None, level: None }
+    }
+
+    /// Never compress
+    pub fn never() -> Rule {
+        Rule { compress: false, encoding: None, level: None }
+    }
+
+    /// Prefer `encoding` over all others the client accepts with the same quality
+    pub fn prefer(encoding: CompressionEncoding) -> Rule {
+        Rule { compress: true, encoding: Some(encoding), level: None }
+    }
+
+    /// Compress with this level instead of the configured or adaptive one
+    pub fn level(mut self, level: RuleLevel) -> Rule {
+        self.level = Some(level);
+        self
+    }
+}
+
+/// The id of the route that handles a request. Routers, or a `BeforeMiddleware`, can store it
+/// in the request extensions to be matched by `CompressionMiddleware::route_rule`.
+pub struct RouteId;
+
+/// What a rule is matched against
+pub enum Matcher {
+    /// A glob matched against the path of the request URL
+    Path(Vec<String>),
+    /// A `RouteId`
+    Route(String),
+}
+
+impl Matcher {
+    /// Parses a glob like `/static/**/*.js`. `*` matches any part of a path segment and `**`
+    /// any number of segments.
+    pub fn path(pattern: &str) -> Matcher {
+        Matcher::Path(segments(pattern).map(String::from).collect())
+    }
+
+    /// Whether a request with these path segments and route id matches
+    pub fn matches(&self, path: &[&str], route: Option<&str>) -> bool {
+        match *self {
+            Matcher::Path(ref pattern) => {
+                let pattern: Vec<&str> = pattern.iter().map(|segment| segment.as_str()).collect();
+                matches_segments(&pattern, path)
+            }
+            Matcher::Route(ref id) => route == Some(id.as_str()),
+        }
+    }
+}
+
+/// The segments of a URL path, like `iron::Url::path` splits them
+pub fn segments(path: &str) -> ::std::str::Split<'_, char> {
+    path.trim_start_matches('/').split('/')
+}
+
+fn matches_segments(pattern: &[&str], path: &[&str]) -> bool {
+    match pattern.split_first() {
+        None => path.is_empty(),
+        Some((&"**", rest)) => (0..path.len() + 1).any(|skipped| matches_segments(rest, &path[skipped..])),
+        Some((first, rest)) => match path.split_first() {
+            Some((segment, path)) => matches_segment(first.as_bytes(), segment.as_bytes()) && matches_segments(rest, path),
+            None => false,
+        },
+    }
+}
+
+fn matches_segment(pattern: &[u8], segment: &[u8]) -> bool {
+    match pattern.split_first() {
+        None => segment.is_empty(),
+        Some((&b'*', rest)) => (0..segment.len() + 1).any(|skipped| matches_segment(rest, &segment[skipped..])),
+        Some((first, rest)) => segment.first() == Some(first) && matches_segment(rest, &segment[1..]),
+    }
+}
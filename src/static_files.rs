@@ -0,0 +1,194 @@
+//! A static file handler that serves precompressed sidecar files.
+//!
+//! For a requested file like `app.js`, the variants `app.js.br`, `app.js.gz` and, with the `zstd`
+//! feature, `app.js.zst` next to it are served to clients that accept their content-coding. Files without an acceptable variant are
+//! compressed on the fly, like `CompressionMiddleware` would. Responses carry a weak `ETag`
+//! derived from the size and modification time of the file.
+
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+use iron::prelude::*;
+use iron::headers::*;
+use iron::url::percent_encoding::percent_decode;
+use iron::{AfterMiddleware, Handler, status};
+use mime_guess;
+
+use config::Config;
+use middleware::CompressionMiddleware;
+use negotiation::{get_header, CompressionEncoding, Decision};
+
+/// The extensions of the sidecar files `precompress` writes, which are not served directly
+const SIDECAR_EXTENSIONS: [&str; 3] = ["br", "gz", "zst"];
+
+/// The extension of the sidecar files compressed using `encoding`, if they are served
+fn sidecar_extension(encoding: CompressionEncoding) -> Option<&'static str> {
+    match encoding {
+        #[cfg(feature = "brotli")]
+        CompressionEncoding::Brotli => Some("br"),
+        #[cfg(feature = "deflate")]
+        CompressionEncoding::Deflate => None,
+        #[cfg(feature = "gzip")]
+        CompressionEncoding::Gzip => Some("gz"),
+        #[cfg(feature = "zstd")]
+        CompressionEncoding::Zstd => Some("zst"),
+    }
+}
+
+/// **Static Files**
+///
+/// Serves the files below a directory. Precompressed `.br`, `.gz` and `.zst` sidecars are
+/// preferred, and chosen between like the middleware chooses an encoding: its rules, predicates,
+/// strategy, priority and BREACH mitigation apply. `.zst` sidecars need the `zstd` feature.
+/// Other files are compressed on the fly by the middleware. Directories are served using their
+/// `index.html`. Sidecars can't be requested directly next to the file they belong to.
+///
+/// # Example
+/// ```rust,no_run
+/// extern crate iron;
+/// extern crate iron_pack;
+///
+/// use iron::prelude::*;
+/// use iron_pack::StaticFiles;
+///
+/// fn main() {
+///     Iron::new(StaticFiles::new("public")).http("localhost:3000").unwrap();
+/// }
+/// ```
+pub struct StaticFiles {
+    root: PathBuf,
+    middleware: CompressionMiddleware,
+    cache_control: Option<Vec<CacheDirective>>,
+}
+
+impl StaticFiles {
+    /// Serves the files below `root`, compressing files without sidecars using the default
+    /// configuration
+    pub fn new<P: Into<PathBuf>>(root: P) -> StaticFiles {
+        StaticFiles::with_config(root, Config::default())
+    }
+
+    /// Serves the files below `root`, compressing files without sidecars using `config`
+    pub fn with_config<P: Into<PathBuf>>(root: P, config: Config) -> StaticFiles {
+        StaticFiles::with_middleware(root, CompressionMiddleware::with_config(config))
+    }
+
+    /// Serves the files below `root`, compressing files without sidecars using `middleware`,
+    /// including its rules
+    pub fn with_middleware<P: Into<PathBuf>>(root: P, middleware: CompressionMiddleware) -> StaticFiles {
+        StaticFiles {
+            root: root.into(),
+            middleware,
+            cache_control: None,
+        }
+    }
+
+    /// Send a `Cache-Control` header with these directives (default: none)
+    pub fn cache_control(mut self, directives: Vec<CacheDirective>) -> StaticFiles {
+        self.cache_control = Some(directives);
+        self
+    }
+
+    /// The file a request asks for. Segments that could leave the root are rejected.
+    fn resolve(&self, req: &Request) -> Option<PathBuf> {
+        let mut path = self.root.clone();
+        for segment in req.url.path() {
+            let segment = percent_decode(segment.as_bytes()).decode_utf8().ok()?;
+            if segment == ".." || segment.contains('/') || segment.contains('\\') {
+                return None;
+            }
+            if !segment.is_empty() && segment != "." {
+                path.push(&*segment);
+            }
+        }
+        if path.is_dir() {
+            path.push("index.html");
+        }
+        if path.is_file() && !is_sidecar(&path) {
+            Some(path)
+        } else {
+            None
+        }
+    }
+}
+
+/// Whether `path` is the sidecar of a file next to it
+fn is_sidecar(path: &Path) -> bool {
+    match path.extension().and_then(|extension| extension.to_str()) {
+        Some(extension) if SIDECAR_EXTENSIONS.contains(&extension) => path.with_extension("").is_file(),
+        _ => false,
+    }
+}
+
+/// The sidecar of `path` compressed using `encoding`, if there is one
+fn sidecar_path(path: &Path, encoding: CompressionEncoding) -> Option<PathBuf> {
+    let extension = sidecar_extension(encoding)?;
+    let mut sidecar = path.as_os_str().to_owned();
+    sidecar.push(".");
+    sidecar.push(extension);
+    let sidecar = PathBuf::from(sidecar);
+    if sidecar.is_file() {
+        Some(sidecar)
+    } else {
+        None
+    }
+}
+
+/// A weak validator from the size and modification time of a file, shared by all its variants
+fn entity_tag(path: &Path) -> io::Result<EntityTag> {
+    let metadata = fs::metadata(path)?;
+    let modified = metadata.modified()?.duration_since(UNIX_EPOCH).map(|modified| modified.as_secs()).unwrap_or(0);
+    Ok(EntityTag::weak(format!("{:x}-{:x}", metadata.len(), modified)))
+}
+
+fn is_not_modified(req: &Request, tag: &EntityTag) -> bool {
+    let if_none_match = match req.headers.get::<IfNoneMatch>() {
+        Some(if_none_match) => if_none_match,
+        None => return false,
+    };
+    match *if_none_match {
+        IfNoneMatch::Any => true,
+        IfNoneMatch::Items(ref tags) => tags.iter().any(|candidate| candidate.weak_eq(tag)),
+    }
+}
+
+fn not_found() -> IronError {
+    IronError::new(io::Error::new(io::ErrorKind::NotFound, "no such file"), status::NotFound)
+}
+
+impl Handler for StaticFiles {
+    fn handle(&self, req: &mut Request) -> IronResult<Response> {
+        let path = self.resolve(req).ok_or_else(not_found)?;
+        let mime = mime_guess::guess_mime_type(&path);
+        let tag = entity_tag(&path).map_err(|_| not_found())?;
+
+        let mut res = if is_not_modified(req, &tag) {
+            Response::with(status::NotModified)
+        } else {
+            let file = File::open(&path).map_err(|_| not_found())?;
+            let res = Response::with((status::Ok, mime.clone(), file));
+            let available: Vec<CompressionEncoding> = CompressionEncoding::all()
+                .into_iter()
+                .filter(|&encoding| sidecar_path(&path, encoding).is_some())
+                .collect();
+            match self.middleware.choose_variant(req, &res, &available) {
+                Decision::Compress(encoding) => {
+                    let sidecar = sidecar_path(&path, encoding).ok_or_else(not_found)?;
+                    let file = File::open(&sidecar).map_err(|_| not_found())?;
+                    let mut res = Response::with((status::Ok, mime, file));
+                    res.headers.set(ContentEncoding(vec![get_header(&encoding)]));
+                    res
+                }
+                Decision::Skip(_) => self.middleware.after(req, res)?,
+            }
+        };
+
+        res.headers.set_raw("Vary", vec![b"Accept-Encoding".to_vec()]);
+        res.headers.set(ETag(tag));
+        if let Some(ref directives) = self.cache_control {
+            res.headers.set(CacheControl(directives.clone()));
+        }
+        Ok(res)
+    }
+}
@@ -0,0 +1,154 @@
+//! Counters describing what the middleware did with the responses it has seen.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::time::Duration;
+
+use negotiation::SkipReason;
+
+/// The reasons with a counter of their own. Predicates are counted by name.
+const SKIP_REASONS: [SkipReason; 15] = [
+    SkipReason::NoBody,
+    SkipReason::AlreadyEncoded,
+    SkipReason::UnknownLength,
+    SkipReason::TooSmall,
+    SkipReason::TooLarge,
+    SkipReason::CrossSite,
+    SkipReason::Sensitive,
+    SkipReason::Rule,
+    SkipReason::NoAcceptEncoding,
+    SkipReason::MalformedAcceptEncoding,
+    SkipReason::QualityZero,
+    SkipReason::NoAcceptableEncoding,
+    SkipReason::NoTransferCoding,
+    SkipReason::Saturated,
+    SkipReason::Padding,
+];
+
+#[derive(Default, Debug)]
+struct Counters {
+    compressed: AtomicUsize,
+    skipped: AtomicUsize,
+    saturated: AtomicUsize,
+    queued: AtomicUsize,
+    in_flight: AtomicUsize,
+    throughput: AtomicU64,
+    skip_reasons: [AtomicUsize; SKIP_REASONS.len()],
+    skipped_by_predicates: Mutex<HashMap<&'static str, usize>>,
+}
+
+/// **Compression Statistics**
+///
+/// A handle to the counters of a `CompressionMiddleware`. Handles are cheap to clone and stay
+/// valid after the middleware is linked into a chain.
+///
+/// # Example
+/// ```rust
+/// use iron_pack::CompressionMiddleware;
+///
+/// let middleware = CompressionMiddleware::new();
+/// let stats = middleware.stats();
+/// // link `middleware` into a chain, then read the counters at any time
+/// assert_eq!(stats.compressed(), 0);
+/// ```
+#[derive(Clone, Default, Debug)]
+pub struct Stats(Arc<Counters>);
+
+impl Stats {
+    /// The number of compressed responses
+    pub fn compressed(&self) -> usize {
+        self.0.compressed.load(Ordering::Relaxed)
+    }
+
+    /// The number of responses sent uncompressed, including saturated ones
+    pub fn skipped(&self) -> usize {
+        self.0.skipped.load(Ordering::Relaxed)
+    }
+
+    /// The number of responses sent uncompressed for the reason with the given name, see
+    /// `SkipReason::name`
+    pub fn skipped_because(&self, reason: &str) -> usize {
+        let skipped = SKIP_REASONS
+            .iter()
+            .position(|known| known.name() == reason)
+            .map_or(0, |index| self.0.skip_reasons[index].load(Ordering::Relaxed));
+        let skipped_by_predicates = self.0.skipped_by_predicates.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        skipped + skipped_by_predicates.get(reason).cloned().unwrap_or(0)
+    }
+
+    /// The number of responses sent uncompressed because the concurrency limit was reached,
+    /// including queued ones that waited in vain
+    pub fn saturated(&self) -> usize {
+        self.0.saturated.load(Ordering::Relaxed)
+    }
+
+    /// The number of responses that waited for a slot of the concurrency limit
+    pub fn queued(&self) -> usize {
+        self.0.queued.load(Ordering::Relaxed)
+    }
+
+    /// The number of responses being compressed right now
+    pub fn in_flight(&self) -> usize {
+        self.0.in_flight.load(Ordering::Relaxed)
+    }
+
+    /// The recent throughput of the encoders in bytes per second, once it was measured. Only
+    /// responses with a `Content-Length` are measured, excluding the time spent sending them.
+    pub fn throughput(&self) -> Option<u64> {
+        match self.0.throughput.load(Ordering::Relaxed) {
+            0 => None,
+            throughput => Some(throughput),
+        }
+    }
+
+    /// Counts a compression as in flight until the returned guard is dropped
+    pub(crate) fn start_compression(&self) -> InFlight {
+        self.0.in_flight.fetch_add(1, Ordering::Relaxed);
+        InFlight(self.clone())
+    }
+
+    /// Folds a measurement into the moving average of the throughput
+    pub(crate) fn record_throughput(&self, bytes: u64, elapsed: Duration) {
+        let nanos = elapsed.as_secs() * 1_000_000_000 + u64::from(elapsed.subsec_nanos());
+        if nanos == 0 {
+            return;
+        }
+        let sample = ((u128::from(bytes) * 1_000_000_000) / u128::from(nanos)).min(u128::from(u64::MAX)) as u64;
+        // Concurrent updates may lose a sample, which is fine for an estimate
+        let previous = self.0.throughput.load(Ordering::Relaxed);
+        let average = if previous == 0 { sample } else { previous - previous / 8 + sample / 8 };
+        self.0.throughput.store(average.max(1), Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_compressed(&self) {
+        self.0.compressed.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_skipped(&self, reason: &SkipReason) {
+        self.0.skipped.fetch_add(1, Ordering::Relaxed);
+        if let Some(index) = SKIP_REASONS.iter().position(|known| known == reason) {
+            self.0.skip_reasons[index].fetch_add(1, Ordering::Relaxed);
+            return;
+        }
+        let mut skipped_by_predicates = self.0.skipped_by_predicates.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        *skipped_by_predicates.entry(reason.name()).or_insert(0) += 1;
+    }
+
+    pub(crate) fn record_saturated(&self) {
+        self.0.saturated.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_queued(&self) {
+        self.0.queued.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// A compression in progress, see `Stats::start_compression`
+pub(crate) struct InFlight(Stats);
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        (self.0).0.in_flight.fetch_sub(1, Ordering::Relaxed);
+    }
+}
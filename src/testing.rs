@@ -0,0 +1,103 @@
+//! Helpers to test handlers and chains with `CompressionMiddleware` installed, based on
+//! [iron-test](https://crates.io/crates/iron-test).
+//!
+//! # Example
+//! ```rust
+//! extern crate iron;
+//! extern crate iron_pack;
+//!
+//! use iron::prelude::*;
+//! use iron_pack::{CompressionEncoding, CompressionMiddleware};
+//! use iron_pack::testing::{assert_encoded, decode_body, post_with_accept_encoding};
+//!
+//! # #[cfg(feature = "gzip")]
+//! fn main() {
+//!     let mut chain = Chain::new(|_: &mut Request| Ok(Response::with((iron::status::Ok, "a".repeat(1000)))));
+//!     chain.link_after(CompressionMiddleware::new());
+//!
+//!     let res = post_with_accept_encoding("", "gzip", &chain).unwrap();
+//!     assert_encoded(&res, CompressionEncoding::Gzip);
+//!     assert_eq!(decode_body(res), "a".repeat(1000).into_bytes());
+//! }
+//! # #[cfg(not(feature = "gzip"))]
+//! # fn main() {}
+//! ```
+
+use std::io::Read;
+use iron::prelude::*;
+use iron::headers::*;
+use iron::{Chain, Handler, status};
+use iron_test::{request, response};
+
+use codec;
+use middleware::CompressionMiddleware;
+use negotiation::{encoding_of, get_header, CompressionEncoding};
+
+const URL: &str = "http://localhost:3000/";
+
+/// Request headers with an `Accept-Encoding` header accepting `encodings` with equal quality
+pub fn accept_encoding(encodings: &[CompressionEncoding]) -> Headers {
+    let mut headers = Headers::new();
+    headers.set(AcceptEncoding(encodings.iter().map(|encoding| qitem(get_header(encoding))).collect()));
+    headers
+}
+
+/// Request headers with a verbatim `Accept-Encoding` header, e.g. `gzip;q=0.5, br`
+pub fn raw_accept_encoding(value: &str) -> Headers {
+    let mut headers = Headers::new();
+    headers.set_raw("Accept-Encoding", vec![value.as_bytes().to_vec()]);
+    headers
+}
+
+/// Sends a `GET` request for `/` with a verbatim `Accept-Encoding` header
+pub fn get_with_accept_encoding<H: Handler>(accept_encoding: &str, handler: &H) -> IronResult<Response> {
+    request::get(URL, raw_accept_encoding(accept_encoding), handler)
+}
+
+/// Sends a `POST` request with `body` to `/` with a verbatim `Accept-Encoding` header
+pub fn post_with_accept_encoding<H: Handler>(body: &str, accept_encoding: &str, handler: &H) -> IronResult<Response> {
+    request::post(URL, raw_accept_encoding(accept_encoding), body, handler)
+}
+
+/// A chain responding with the request body, with `middleware` installed
+pub fn compressed_echo_chain(middleware: CompressionMiddleware) -> Chain {
+    let mut chain = Chain::new(|req: &mut Request| {
+        let mut body = Vec::new();
+        req.body.read_to_end(&mut body).map_err(|err| IronError::new(err, status::BadRequest))?;
+        Ok(Response::with((status::Ok, body)))
+    });
+    chain.link_after(middleware);
+    chain
+}
+
+/// Asserts that the response is encoded using `encoding`
+pub fn assert_encoded(res: &Response, encoding: CompressionEncoding) {
+    match res.headers.get::<ContentEncoding>() {
+        Some(content_encoding) if content_encoding.0 == [get_header(&encoding)] => {}
+        other => panic!("expected a response encoded using {}, got Content-Encoding {:?}", encoding.content_coding(), other),
+    }
+}
+
+/// Asserts that the response is not encoded
+pub fn assert_not_encoded(res: &Response) {
+    if let Some(content_encoding) = res.headers.get::<ContentEncoding>() {
+        panic!("expected a response that is not encoded, got Content-Encoding {}", content_encoding);
+    }
+}
+
+/// The body of the response, decoded according to its `Content-Encoding`. Panics on encodings
+/// iron-pack can't decode.
+pub fn decode_body(res: Response) -> Vec<u8> {
+    let encoding = match res.headers.get::<ContentEncoding>() {
+        None => None,
+        Some(content_encoding) => match encoding_of(&content_encoding.0) {
+            Some(encoding) => Some(encoding),
+            None => panic!("can't decode Content-Encoding {}", content_encoding),
+        },
+    };
+    let body = response::extract_body_to_bytes(res);
+    match encoding {
+        Some(encoding) => codec::decode(encoding, &body).unwrap_or_else(|err| panic!("invalid {} body: {}", encoding.content_coding(), err)),
+        None => body,
+    }
+}